@@ -1,7 +1,7 @@
 use crate::rack::*;
 use crate::utils::{interp, interp_inv};
 use crate::{build, props, tag};
-use std::sync::Arc;
+use alloc::sync::Arc;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Adsr {
@@ -1,7 +1,41 @@
 use crate::rack::*;
 use crate::{build, props, tag};
-use std::f32::consts::PI;
-use std::sync::Arc;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num::Float;
+
+/// Return the 5 coefficients cached in `state` starting at `slot`, calling
+/// `compute` only when `key` differs from the key they were computed for.
+/// Uses `key.len() + 5` slots; the key is stored first.
+fn cached<F: FnOnce() -> [f32; 5]>(
+    state: &mut State,
+    tag: Tag,
+    slot: usize,
+    key: &[f32],
+    compute: F,
+) -> [f32; 5] {
+    let k = key.len();
+    if key
+        .iter()
+        .enumerate()
+        .all(|(i, v)| state[(tag, slot + i)] == *v)
+    {
+        let mut c = [0.0; 5];
+        for (i, v) in c.iter_mut().enumerate() {
+            *v = state[(tag, slot + k + i)];
+        }
+        return c;
+    }
+    let c = compute();
+    for (i, v) in key.iter().enumerate() {
+        state[(tag, slot + i)] = *v;
+    }
+    for (i, v) in c.iter().enumerate() {
+        state[(tag, slot + k + i)] = *v;
+    }
+    c
+}
 
 #[derive(Debug, Copy, Clone)]
 pub struct Lpf {
@@ -29,6 +63,10 @@ impl Lpf {
 
 impl Signal for Lpf {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -45,12 +83,14 @@ impl Signal for Lpf {
         }
         let tag = self.tag;
         let q = self.q(controls, outputs);
-        let phi = 2.0 * PI * cut_off / sample_rate;
-        let b2 = (2.0 * q - phi.sin()) / (2.0 * q + phi.sin());
-        let b1 = -(1.0 + b2) * phi.cos();
-        let a0 = 0.25 * (1.0 + b1 + b2);
-        let a1 = 2.0 * a0;
-        outputs[(tag, 0)] = a0 * x0 + a1 * state[(tag, 0)] + a0 * state[(tag, 1)]
+        let [a0, a1, a2, b1, b2] = cached(state, tag, 4, &[cut_off, q, sample_rate], || {
+            let phi = 2.0 * PI * cut_off / sample_rate;
+            let b2 = (2.0 * q - phi.sin()) / (2.0 * q + phi.sin());
+            let b1 = -(1.0 + b2) * phi.cos();
+            let a0 = 0.25 * (1.0 + b1 + b2);
+            [a0, 2.0 * a0, a0, b1, b2]
+        });
+        outputs[(tag, 0)] = a0 * x0 + a1 * state[(tag, 0)] + a2 * state[(tag, 1)]
             - b1 * state[(tag, 2)]
             - b2 * state[(tag, 3)];
         state[(tag, 1)] = state[(tag, 0)];
@@ -123,6 +163,10 @@ impl Hpf {
 
 impl Signal for Hpf {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -139,12 +183,14 @@ impl Signal for Hpf {
         }
         let tag = self.tag;
         let q = self.q(controls, outputs);
-        let phi = 2.0 * PI * cut_off / sample_rate;
-        let b2 = (2.0 * q - phi.sin()) / (2.0 * q + phi.sin());
-        let b1 = -(1.0 + b2) * phi.cos();
-        let a0 = 0.25 * (1.0 - b1 + b2);
-        let a1 = -2.0 * a0;
-        outputs[(tag, 0)] = a0 * x0 + a1 * state[(tag, 0)] + a0 * state[(tag, 1)]
+        let [a0, a1, a2, b1, b2] = cached(state, tag, 4, &[cut_off, q, sample_rate], || {
+            let phi = 2.0 * PI * cut_off / sample_rate;
+            let b2 = (2.0 * q - phi.sin()) / (2.0 * q + phi.sin());
+            let b1 = -(1.0 + b2) * phi.cos();
+            let a0 = 0.25 * (1.0 - b1 + b2);
+            [a0, -2.0 * a0, a0, b1, b2]
+        });
+        outputs[(tag, 0)] = a0 * x0 + a1 * state[(tag, 0)] + a2 * state[(tag, 1)]
             - b1 * state[(tag, 2)]
             - b2 * state[(tag, 3)];
         state[(tag, 1)] = state[(tag, 0)];
@@ -216,6 +262,10 @@ impl Bpf {
 
 impl Signal for Bpf {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -232,12 +282,13 @@ impl Signal for Bpf {
         }
         let tag = self.tag;
         let q = self.q(controls, outputs);
-        let phi = 2.0 * PI * cut_off / sample_rate;
-        let b2 = (PI / 4.0 - phi / (2.0 * q)).tan();
-        let b1 = -(1.0 + b2) * phi.cos();
-        let a0 = 0.5 * (1.0 - b2);
-        let a1 = 0.0;
-        let a2 = -a0;
+        let [a0, a1, a2, b1, b2] = cached(state, tag, 4, &[cut_off, q, sample_rate], || {
+            let phi = 2.0 * PI * cut_off / sample_rate;
+            let b2 = (PI / 4.0 - phi / (2.0 * q)).tan();
+            let b1 = -(1.0 + b2) * phi.cos();
+            let a0 = 0.5 * (1.0 - b2);
+            [a0, 0.0, -a0, b1, b2]
+        });
         outputs[(tag, 0)] = a0 * x0 + a1 * state[(tag, 0)] + a2 * state[(tag, 1)]
             - b1 * state[(tag, 2)]
             - b2 * state[(tag, 3)];
@@ -310,6 +361,10 @@ impl Notch {
 
 impl Signal for Notch {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -326,12 +381,14 @@ impl Signal for Notch {
         }
         let tag = self.tag;
         let q = self.q(controls, outputs);
-        let phi = 2.0 * PI * cut_off / sample_rate;
-        let b2 = (PI / 4.0 - phi / (2.0 * q)).tan();
-        let b1 = -(1.0 + b2) * phi.cos();
-        let a0 = 0.5 * (1.0 + b2);
-        let a1 = b1;
-        outputs[(tag, 0)] = a0 * x0 + a1 * state[(tag, 0)] + a0 * state[(tag, 1)]
+        let [a0, a1, a2, b1, b2] = cached(state, tag, 4, &[cut_off, q, sample_rate], || {
+            let phi = 2.0 * PI * cut_off / sample_rate;
+            let b2 = (PI / 4.0 - phi / (2.0 * q)).tan();
+            let b1 = -(1.0 + b2) * phi.cos();
+            let a0 = 0.5 * (1.0 + b2);
+            [a0, b1, a0, b1, b2]
+        });
+        outputs[(tag, 0)] = a0 * x0 + a1 * state[(tag, 0)] + a2 * state[(tag, 1)]
             - b1 * state[(tag, 2)]
             - b2 * state[(tag, 3)];
         state[(tag, 1)] = state[(tag, 0)];
@@ -377,6 +434,561 @@ impl NotchBuilder {
         notch
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LadderSlope {
+    Db12,
+    Db24,
+}
+
+/// Moog style 4-pole ladder lowpass with zero delay feedback. The input is
+/// saturated by `tanh(drive * x)` and the feedback loop by another `tanh`, so
+/// `resonance` can go up to and past self-oscillation at about 1. Output 0 is
+/// the selected slope, outputs 1 and 2 the 12 and 24 dB/octave taps.
+// https://www.native-instruments.com/fileadmin/ni_media/downloads/pdf/VAFilterDesign_2.1.0.pdf
+#[derive(Debug, Copy, Clone)]
+pub struct Ladder {
+    tag: Tag,
+    wave: Tag,
+    slope: LadderSlope,
+}
+
+impl Ladder {
+    pub fn new(tag: Tag, wave: Tag, slope: LadderSlope) -> Self {
+        Self { tag, wave, slope }
+    }
+    props!(cutoff, set_cutoff, 0);
+    props!(resonance, set_resonance, 1);
+    props!(drive, set_drive, 2);
+}
+
+impl Signal for Ladder {
+    tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let cut_off = self
+            .cutoff(controls, outputs)
+            .clamp(1.0, 0.49 * sample_rate);
+        let k = 4.0 * self.resonance(controls, outputs);
+        let x = (self.drive(controls, outputs) * outputs[(self.wave, 0)]).tanh();
+        let g = (PI * cut_off / sample_rate).tan();
+        let big_g = g / (1.0 + g);
+        // Each one pole stage is y = G x + s / (1 + g), so the output of the
+        // ladder is G^4 u + sigma and the feedback can be solved for directly.
+        let mut sigma = 0.0;
+        for i in 0..4 {
+            sigma = sigma * big_g + state[(tag, i)] / (1.0 + g);
+        }
+        let mut y = ((x - k * sigma) / (1.0 + k * big_g.powi(4))).tanh();
+        let mut taps = [0.0; 4];
+        for (i, tap) in taps.iter_mut().enumerate() {
+            let v = (y - state[(tag, i)]) * big_g;
+            y = v + state[(tag, i)];
+            state[(tag, i)] = y + v;
+            *tap = y;
+        }
+        outputs[(tag, 1)] = taps[1];
+        outputs[(tag, 2)] = taps[3];
+        outputs[(tag, 0)] = match self.slope {
+            LadderSlope::Db12 => taps[1],
+            LadderSlope::Db24 => taps[3],
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LadderBuilder {
+    wave: Tag,
+    slope: LadderSlope,
+    cut_off: Control,
+    resonance: Control,
+    drive: Control,
+}
+
+impl LadderBuilder {
+    pub fn new(wave: Tag) -> Self {
+        Self {
+            wave,
+            slope: LadderSlope::Db24,
+            cut_off: 1_000.0.into(),
+            resonance: 0.0.into(),
+            drive: 1.0.into(),
+        }
+    }
+
+    pub fn slope(&mut self, value: LadderSlope) -> &mut Self {
+        self.slope = value;
+        self
+    }
+    build!(cut_off);
+    build!(resonance);
+    build!(drive);
+
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Ladder> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.cut_off;
+        controls[(n, 1)] = self.resonance;
+        controls[(n, 2)] = self.drive;
+        let ladder = Arc::new(Ladder::new(n.into(), self.wave, self.slope));
+        rack.push(ladder.clone());
+        ladder
+    }
+}
+
+/// Topology preserving transform state variable filter, stable under audio
+/// rate cutoff modulation. Outputs 1 to 5 are lowpass, highpass, bandpass
+/// (unity gain at the cutoff), notch and peak. Output 0 morphs from lowpass
+/// at `morph` 0 through bandpass at 0.5 to highpass at 1.
+// https://cytomic.com/files/dsp/SvfLinearTrapOptimised2.pdf
+#[derive(Debug, Copy, Clone)]
+pub struct Svf {
+    tag: Tag,
+    wave: Tag,
+}
+
+impl Svf {
+    pub fn new(tag: Tag, wave: Tag) -> Self {
+        Self { tag, wave }
+    }
+    props!(cutoff, set_cutoff, 0);
+    props!(q, set_q, 1);
+    props!(morph, set_morph, 2);
+}
+
+impl Signal for Svf {
+    tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let x = outputs[(self.wave, 0)];
+        let cut_off = self
+            .cutoff(controls, outputs)
+            .clamp(1.0, 0.49 * sample_rate);
+        let r = 0.5 / self.q(controls, outputs).max(0.01);
+        let g = (PI * cut_off / sample_rate).tan();
+        let (s1, s2) = (state[(tag, 0)], state[(tag, 1)]);
+        let hp = (x - (2.0 * r + g) * s1 - s2) / (1.0 + 2.0 * r * g + g * g);
+        let bp = g * hp + s1;
+        let lp = g * bp + s2;
+        state[(tag, 0)] = g * hp + bp;
+        state[(tag, 1)] = g * bp + lp;
+        let band = 2.0 * r * bp;
+        let morph = 2.0 * self.morph(controls, outputs).clamp(0.0, 1.0);
+        outputs[(tag, 0)] = if morph < 1.0 {
+            lp + morph * (band - lp)
+        } else {
+            band + (morph - 1.0) * (hp - band)
+        };
+        outputs[(tag, 1)] = lp;
+        outputs[(tag, 2)] = hp;
+        outputs[(tag, 3)] = band;
+        outputs[(tag, 4)] = lp + hp;
+        outputs[(tag, 5)] = lp - hp;
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SvfBuilder {
+    wave: Tag,
+    cut_off: Control,
+    q: Control,
+    morph: Control,
+}
+
+impl SvfBuilder {
+    pub fn new(wave: Tag) -> Self {
+        Self {
+            wave,
+            cut_off: 1_000.0.into(),
+            q: 0.707.into(),
+            morph: 0.0.into(),
+        }
+    }
+
+    build!(cut_off);
+    build!(q);
+    build!(morph);
+
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Svf> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.cut_off;
+        controls[(n, 1)] = self.q;
+        controls[(n, 2)] = self.morph;
+        let svf = Arc::new(Svf::new(n.into(), self.wave));
+        rack.push(svf.clone());
+        svf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EqShape {
+    LowShelf,
+    HighShelf,
+    Peak,
+    /// Cuts the lows by `gain / 2` and boosts the highs by `gain / 2`
+    /// around `freq`.
+    Tilt,
+}
+
+/// Biquad coefficients `[b0, b1, b2, a1, a2]` normalized by `a0`, from the
+/// RBJ Audio EQ Cookbook. `gain` is in dB.
+// https://www.w3.org/TR/audio-eq-cookbook/
+pub fn eq_coefficients(shape: EqShape, freq: f32, gain: f32, q: f32, sample_rate: f32) -> [f32; 5] {
+    let a = (10.0f32).powf(gain / 40.0);
+    let w0 = 2.0 * PI * freq.clamp(1.0, 0.49 * sample_rate) / sample_rate;
+    let (sin, cos) = w0.sin_cos();
+    let alpha = sin / (2.0 * q.max(0.01));
+    let beta = 2.0 * a.sqrt() * alpha;
+    let (b, a) = match shape {
+        EqShape::LowShelf => (
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * cos + beta,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - beta,
+            ],
+        ),
+        EqShape::HighShelf | EqShape::Tilt => (
+            [
+                a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) - (a - 1.0) * cos + beta,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - beta,
+            ],
+        ),
+        EqShape::Peak => (
+            [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+        ),
+    };
+    // A tilt is a high shelf by the full gain, turned down by half of it.
+    let scale = if shape == EqShape::Tilt {
+        (10.0f32).powf(-gain / 40.0)
+    } else {
+        1.0
+    };
+    [
+        scale * b[0] / a[0],
+        scale * b[1] / a[0],
+        scale * b[2] / a[0],
+        a[1] / a[0],
+        a[2] / a[0],
+    ]
+}
+
+/// Run one sample through a transposed direct form II biquad whose two
+/// delays are kept in state slots `slot` and `slot + 1`.
+fn biquad(c: [f32; 5], x: f32, state: &mut State, tag: Tag, slot: usize) -> f32 {
+    let y = c[0] * x + state[(tag, slot)];
+    state[(tag, slot)] = c[1] * x - c[3] * y + state[(tag, slot + 1)];
+    state[(tag, slot + 1)] = c[2] * x - c[4] * y;
+    y
+}
+
+/// A shelving, peaking or tilt EQ filter, `gain` is in dB.
+#[derive(Debug, Copy, Clone)]
+pub struct EqFilter {
+    tag: Tag,
+    wave: Tag,
+    shape: EqShape,
+}
+
+impl EqFilter {
+    pub fn new(tag: Tag, wave: Tag, shape: EqShape) -> Self {
+        Self { tag, wave, shape }
+    }
+    props!(freq, set_freq, 0);
+    props!(gain, set_gain, 1);
+    props!(q, set_q, 2);
+}
+
+impl Signal for EqFilter {
+    tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let freq = self.freq(controls, outputs);
+        let gain = self.gain(controls, outputs);
+        let q = self.q(controls, outputs);
+        let c = cached(state, self.tag, 2, &[freq, gain, q, sample_rate], || {
+            eq_coefficients(self.shape, freq, gain, q, sample_rate)
+        });
+        let x = outputs[(self.wave, 0)];
+        outputs[(self.tag, 0)] = biquad(c, x, state, self.tag, 0);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct EqFilterBuilder {
+    wave: Tag,
+    shape: EqShape,
+    freq: Control,
+    gain: Control,
+    q: Control,
+}
+
+impl EqFilterBuilder {
+    pub fn new(wave: Tag, shape: EqShape) -> Self {
+        Self {
+            wave,
+            shape,
+            freq: 1_000.0.into(),
+            gain: 0.0.into(),
+            q: 0.707.into(),
+        }
+    }
+
+    build!(freq);
+    build!(gain);
+    build!(q);
+
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<EqFilter> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.freq;
+        controls[(n, 1)] = self.gain;
+        controls[(n, 2)] = self.q;
+        let eq = Arc::new(EqFilter::new(n.into(), self.wave, self.shape));
+        rack.push(eq.clone());
+        eq
+    }
+}
+
+pub fn low_shelf(wave: Tag) -> EqFilterBuilder {
+    EqFilterBuilder::new(wave, EqShape::LowShelf)
+}
+
+pub fn high_shelf(wave: Tag) -> EqFilterBuilder {
+    EqFilterBuilder::new(wave, EqShape::HighShelf)
+}
+
+pub fn peak(wave: Tag) -> EqFilterBuilder {
+    EqFilterBuilder::new(wave, EqShape::Peak)
+}
+
+pub fn tilt(wave: Tag) -> EqFilterBuilder {
+    EqFilterBuilder::new(wave, EqShape::Tilt)
+}
+
+/// Maximum number of bands in a `ParametricEq`, limited by the state each
+/// band needs.
+pub const MAX_BANDS: usize = 5;
+
+/// Length of the `ParametricEq` bypass crossfade in seconds.
+pub const BYPASS_FADE: f32 = 0.005;
+
+/// A multi-band EQ, a chain of `EqFilter`s in one module. Band `i` has the
+/// controls freq, gain, q and bypass starting at `4 * i`. A band is bypassed
+/// while its bypass gate is above 0.5, crossfading between its filtered and
+/// dry signal over `BYPASS_FADE` seconds.
+#[derive(Debug, Clone)]
+pub struct ParametricEq {
+    tag: Tag,
+    wave: Tag,
+    bands: Vec<EqShape>,
+}
+
+impl ParametricEq {
+    pub fn new(tag: Tag, wave: Tag, bands: Vec<EqShape>) -> Self {
+        assert!(
+            bands.len() <= MAX_BANDS,
+            "Max number of bands is {}",
+            MAX_BANDS
+        );
+        Self { tag, wave, bands }
+    }
+
+    pub fn bands(&self) -> &[EqShape] {
+        &self.bands
+    }
+
+    fn band_control(&self, controls: &Controls, outputs: &Outputs, band: usize, n: usize) -> f32 {
+        let inp = controls[(self.tag, 4 * band + n)];
+        outputs.value(inp).unwrap()
+    }
+
+    pub fn freq(&self, controls: &Controls, outputs: &Outputs, band: usize) -> f32 {
+        self.band_control(controls, outputs, band, 0)
+    }
+
+    pub fn set_freq(&self, controls: &mut Controls, band: usize, value: Control) {
+        controls[(self.tag, 4 * band)] = value;
+    }
+
+    pub fn gain(&self, controls: &Controls, outputs: &Outputs, band: usize) -> f32 {
+        self.band_control(controls, outputs, band, 1)
+    }
+
+    pub fn set_gain(&self, controls: &mut Controls, band: usize, value: Control) {
+        controls[(self.tag, 4 * band + 1)] = value;
+    }
+
+    pub fn q(&self, controls: &Controls, outputs: &Outputs, band: usize) -> f32 {
+        self.band_control(controls, outputs, band, 2)
+    }
+
+    pub fn set_q(&self, controls: &mut Controls, band: usize, value: Control) {
+        controls[(self.tag, 4 * band + 2)] = value;
+    }
+
+    pub fn bypass(&self, controls: &Controls, outputs: &Outputs, band: usize) -> f32 {
+        self.band_control(controls, outputs, band, 3)
+    }
+
+    pub fn set_bypass(&self, controls: &mut Controls, band: usize, value: Control) {
+        controls[(self.tag, 4 * band + 3)] = value;
+    }
+}
+
+impl Signal for ParametricEq {
+    tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        // Each band caches its key and coefficients in 8 slots after the
+        // delays. A new sample rate invalidates them all. After the sample
+        // rate each band keeps how far it has faded to the dry signal.
+        let cache = |band: usize| 2 * MAX_BANDS + 8 * band;
+        let fade = |band: usize| cache(MAX_BANDS) + 1 + band;
+        if state[(tag, cache(MAX_BANDS))] != sample_rate {
+            state[(tag, cache(MAX_BANDS))] = sample_rate;
+            for band in 0..self.bands.len() {
+                state[(tag, cache(band))] = f32::NAN;
+            }
+        }
+        let mut x = outputs[(self.wave, 0)];
+        for (band, shape) in self.bands.iter().enumerate() {
+            let target = if self.bypass(controls, outputs, band) > 0.5 {
+                1.0
+            } else {
+                0.0
+            };
+            let step = 1.0 / (BYPASS_FADE * sample_rate);
+            let dry = state[(tag, fade(band))];
+            let dry = if dry < target {
+                (dry + step).min(target)
+            } else {
+                (dry - step).max(target)
+            };
+            state[(tag, fade(band))] = dry;
+            let freq = self.freq(controls, outputs, band);
+            let gain = self.gain(controls, outputs, band);
+            let q = self.q(controls, outputs, band);
+            let c = cached(state, tag, cache(band), &[freq, gain, q], || {
+                eq_coefficients(*shape, freq, gain, q, sample_rate)
+            });
+            // The filter keeps running while bypassed so it comes back in
+            // without a click.
+            x = lerp(biquad(c, x, state, tag, 2 * band), x, dry);
+        }
+        outputs[(self.tag, 0)] = x;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParametricEqBuilder {
+    wave: Tag,
+    bands: Vec<EqShape>,
+    freq: Vec<Control>,
+    gain: Vec<Control>,
+    q: Vec<Control>,
+    bypass: Vec<Control>,
+}
+
+impl ParametricEqBuilder {
+    pub fn new(wave: Tag, bands: Vec<EqShape>) -> Self {
+        let n = bands.len();
+        Self {
+            wave,
+            bands,
+            freq: vec![1_000.0.into(); n],
+            gain: vec![0.0.into(); n],
+            q: vec![0.707.into(); n],
+            bypass: vec![0.0.into(); n],
+        }
+    }
+
+    pub fn freq<T: Into<Control>>(&mut self, band: usize, value: T) -> &mut Self {
+        self.freq[band] = value.into();
+        self
+    }
+
+    pub fn gain<T: Into<Control>>(&mut self, band: usize, value: T) -> &mut Self {
+        self.gain[band] = value.into();
+        self
+    }
+
+    pub fn q<T: Into<Control>>(&mut self, band: usize, value: T) -> &mut Self {
+        self.q[band] = value.into();
+        self
+    }
+
+    pub fn bypass<T: Into<Control>>(&mut self, band: usize, value: T) -> &mut Self {
+        self.bypass[band] = value.into();
+        self
+    }
+
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<ParametricEq> {
+        let n = rack.num_modules();
+        for band in 0..self.bands.len() {
+            controls[(n, 4 * band)] = self.freq[band];
+            controls[(n, 4 * band + 1)] = self.gain[band];
+            controls[(n, 4 * band + 2)] = self.q[band];
+            controls[(n, 4 * band + 3)] = self.bypass[band];
+        }
+        let eq = Arc::new(ParametricEq::new(n.into(), self.wave, self.bands.clone()));
+        rack.push(eq.clone());
+        eq
+    }
+}
+
 /// Lowpass-Feedback Comb Filter
 // https://ccrma.stanford.edu/~jos/pasp/Lowpass_Feedback_Comb_Filter.html
 #[derive(Clone)]
@@ -399,6 +1011,19 @@ impl Comb {
 
 impl Signal for Comb {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+    fn latency(
+        &self,
+        _controls: &Controls,
+        _outputs: &Outputs,
+        buffers: &Buffers,
+        _sample_rate: f32,
+    ) -> f32 {
+        buffers.buffers(self.tag).len() as f32
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -474,6 +1099,10 @@ impl AllPass {
 
 impl Signal for AllPass {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
     fn signal(
         &self,
         _controls: &Controls,
@@ -1,6 +1,9 @@
 use crate::{build, props, tag};
 use crate::{envelopes::*, filters::LpfBuilder, operators::*, rack::*};
-use std::sync::Arc;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num::Float;
 
 #[derive(Clone)]
 pub struct WaveGuide {
@@ -115,3 +118,383 @@ impl WaveGuideBuilder {
         wg
     }
 }
+
+/// Maximum number of operators in an `FmVoice`.
+pub const MAX_OPERATORS: usize = 6;
+
+/// An operator routing for an `FmVoice`. Operators are numbered from 0 and
+/// evaluated from the highest to the lowest, so a route from a lower to a
+/// higher operator uses the output from the previous sample.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Algorithm {
+    carriers: u8,
+    modulators: [u8; MAX_OPERATORS],
+    feedback: [u8; MAX_OPERATORS],
+}
+
+/// Carriers and `(modulator, carrier)` routes of an algorithm.
+type Routing = (&'static [usize], &'static [(usize, usize)]);
+
+/// The DX7 algorithms as (carriers, routes) using the 1 based operator
+/// numbers from the manual. Feedback is set per operator, so algorithms that
+/// only differ in where the feedback goes have the same routes, except for the
+/// loops in `DX7_LOOPS`.
+const DX7: [Routing; 32] = [
+    (&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 5)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 5)]),
+    (&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 5)]),
+    (&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 5)]),
+    (&[1, 3, 5], &[(2, 1), (4, 3), (6, 5)]),
+    (&[1, 3, 5], &[(2, 1), (4, 3), (6, 5)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 5)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 5)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 5)]),
+    (&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 4)]),
+    (&[1, 4], &[(2, 1), (3, 2), (5, 4), (6, 4)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 3)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 3), (6, 3)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 4)]),
+    (&[1, 3], &[(2, 1), (4, 3), (5, 4), (6, 4)]),
+    (&[1], &[(2, 1), (3, 1), (5, 1), (4, 3), (6, 5)]),
+    (&[1], &[(2, 1), (3, 1), (5, 1), (4, 3), (6, 5)]),
+    (&[1], &[(2, 1), (3, 1), (4, 1), (5, 4), (6, 5)]),
+    (&[1, 4, 5], &[(2, 1), (3, 2), (6, 4), (6, 5)]),
+    (&[1, 2, 4], &[(3, 1), (3, 2), (5, 4), (6, 4)]),
+    (&[1, 2, 4, 5], &[(3, 1), (3, 2), (6, 4), (6, 5)]),
+    (&[1, 3, 4, 5], &[(2, 1), (6, 3), (6, 4), (6, 5)]),
+    (&[1, 2, 4, 5], &[(3, 2), (6, 4), (6, 5)]),
+    (&[1, 2, 3, 4, 5], &[(6, 3), (6, 4), (6, 5)]),
+    (&[1, 2, 3, 4, 5], &[(6, 4), (6, 5)]),
+    (&[1, 2, 4], &[(3, 2), (5, 4), (6, 4)]),
+    (&[1, 2, 4], &[(3, 2), (5, 4), (6, 4)]),
+    (&[1, 3, 6], &[(2, 1), (4, 3), (5, 4)]),
+    (&[1, 2, 3, 5], &[(4, 3), (6, 5)]),
+    (&[1, 2, 3, 6], &[(4, 3), (5, 4)]),
+    (&[1, 2, 3, 4, 5], &[(6, 5)]),
+    (&[1, 2, 3, 4, 5, 6], &[]),
+];
+
+/// The DX7 algorithms whose feedback runs through more than one operator, as
+/// (algorithm, from, to).
+const DX7_LOOPS: [(usize, usize, usize); 2] = [(4, 4, 6), (6, 5, 6)];
+
+/// The 4 operator algorithms of the DX21 and TX81Z, 1 based like `DX7`.
+const FOUR_OP: [Routing; 8] = [
+    (&[1], &[(2, 1), (3, 2), (4, 3)]),
+    (&[1], &[(2, 1), (3, 2), (4, 2)]),
+    (&[1], &[(2, 1), (3, 2), (4, 1)]),
+    (&[1], &[(2, 1), (3, 1), (4, 3)]),
+    (&[1, 3], &[(2, 1), (4, 3)]),
+    (&[1, 2, 3], &[(4, 1), (4, 2), (4, 3)]),
+    (&[1, 2, 3], &[(4, 3)]),
+    (&[1, 2, 3, 4], &[]),
+];
+
+fn check_operator(op: usize) {
+    assert!(
+        op < MAX_OPERATORS,
+        "Operators are numbered from 0 to {}, not {}",
+        MAX_OPERATORS - 1,
+        op
+    );
+}
+
+impl Algorithm {
+    /// An algorithm from its carriers and `(modulator, carrier)` routes, with
+    /// each operator feeding back into itself. Panics if an operator is not
+    /// below `MAX_OPERATORS`.
+    pub fn new(carriers: &[usize], routes: &[(usize, usize)]) -> Self {
+        for &op in carriers
+            .iter()
+            .chain(routes.iter().flat_map(|(m, c)| [m, c]))
+        {
+            check_operator(op);
+        }
+        let mut algorithm = Self {
+            carriers: 0,
+            modulators: [0; MAX_OPERATORS],
+            feedback: [0; MAX_OPERATORS],
+        };
+        for (op, f) in algorithm.feedback.iter_mut().enumerate() {
+            *f = 1 << op;
+        }
+        for &c in carriers {
+            algorithm.carriers |= 1 << c;
+        }
+        for &(m, c) in routes {
+            algorithm.modulators[c] |= 1 << m;
+        }
+        algorithm
+    }
+    fn one_based(table: Routing) -> Self {
+        let carriers: Vec<usize> = table.0.iter().map(|c| c - 1).collect();
+        let routes: Vec<(usize, usize)> = table.1.iter().map(|(m, c)| (m - 1, c - 1)).collect();
+        Self::new(&carriers, &routes)
+    }
+    /// Replace the feedback of operator `to` with the output of `from`,
+    /// scaled by the feedback amount of `to`.
+    pub fn feedback_loop(mut self, from: usize, to: usize) -> Self {
+        check_operator(from);
+        check_operator(to);
+        self.feedback[to] = 1 << from;
+        self
+    }
+    /// DX7 algorithm `n` from 1 to 32.
+    pub fn dx7(n: usize) -> Self {
+        DX7_LOOPS
+            .iter()
+            .filter(|l| l.0 == n)
+            .fold(Self::one_based(DX7[n - 1]), |a, l| {
+                a.feedback_loop(l.1 - 1, l.2 - 1)
+            })
+    }
+    /// DX21 / TX81Z algorithm `n` from 1 to 8.
+    pub fn four_op(n: usize) -> Self {
+        Self::one_based(FOUR_OP[n - 1])
+    }
+    pub fn is_carrier(&self, op: usize) -> bool {
+        self.carriers & (1 << op) != 0
+    }
+    pub fn modulates(&self, modulator: usize, carrier: usize) -> bool {
+        self.modulators[carrier] & (1 << modulator) != 0
+    }
+    /// True if the output of `from` is fed back into `to`.
+    pub fn feeds_back(&self, from: usize, to: usize) -> bool {
+        self.feedback[to] & (1 << from) != 0
+    }
+}
+
+/// A multi operator FM (phase modulation) voice. Each operator is a sine at
+/// `ratio * hz`, or at `fixed` hz when that is not 0, scaled by its `level`
+/// and envelope. Modulator outputs are added to the phase in cycles, as is
+/// `feedback` times the average of the last two outputs of the operator, or of
+/// the one that closes its feedback loop. The
+/// `algorithm` control selects one of the voice's algorithms, numbered from 1
+/// like `Algorithm::dx7` and clamped to the ones available.
+#[derive(Clone)]
+pub struct FmVoice {
+    tag: Tag,
+    envelopes: Vec<Arc<Adsr>>,
+    algorithms: Vec<Algorithm>,
+}
+
+impl FmVoice {
+    pub fn new<T: Into<Tag>>(
+        tag: T,
+        envelopes: Vec<Arc<Adsr>>,
+        algorithms: Vec<Algorithm>,
+    ) -> Self {
+        Self {
+            tag: tag.into(),
+            envelopes,
+            algorithms,
+        }
+    }
+    props!(hz, set_hz, 0);
+    props!(amplitude, set_amplitude, 1);
+
+    pub fn algorithm(&self, controls: &Controls, outputs: &Outputs) -> usize {
+        let inp = controls[(self.tag, 2)];
+        outputs.integer(inp).expect("algorithm must be Control::I")
+    }
+
+    pub fn set_algorithm(&self, controls: &mut Controls, value: usize) {
+        controls[(self.tag, 2)] = value.into();
+    }
+
+    pub fn operators(&self) -> usize {
+        self.envelopes.len()
+    }
+
+    pub fn envelope(&self, op: usize) -> &Arc<Adsr> {
+        &self.envelopes[op]
+    }
+
+    fn op_control(&self, controls: &Controls, outputs: &Outputs, op: usize, n: usize) -> f32 {
+        let inp = controls[(self.tag, 3 + 4 * op + n)];
+        outputs.value(inp).unwrap()
+    }
+
+    pub fn ratio(&self, controls: &Controls, outputs: &Outputs, op: usize) -> f32 {
+        self.op_control(controls, outputs, op, 0)
+    }
+
+    pub fn set_ratio(&self, controls: &mut Controls, op: usize, value: Control) {
+        controls[(self.tag, 3 + 4 * op)] = value;
+    }
+
+    pub fn level(&self, controls: &Controls, outputs: &Outputs, op: usize) -> f32 {
+        self.op_control(controls, outputs, op, 1)
+    }
+
+    pub fn set_level(&self, controls: &mut Controls, op: usize, value: Control) {
+        controls[(self.tag, 3 + 4 * op + 1)] = value;
+    }
+
+    pub fn feedback(&self, controls: &Controls, outputs: &Outputs, op: usize) -> f32 {
+        self.op_control(controls, outputs, op, 2)
+    }
+
+    pub fn set_feedback(&self, controls: &mut Controls, op: usize, value: Control) {
+        controls[(self.tag, 3 + 4 * op + 2)] = value;
+    }
+
+    pub fn fixed(&self, controls: &Controls, outputs: &Outputs, op: usize) -> f32 {
+        self.op_control(controls, outputs, op, 3)
+    }
+
+    pub fn set_fixed(&self, controls: &mut Controls, op: usize, value: Control) {
+        controls[(self.tag, 3 + 4 * op + 3)] = value;
+    }
+
+    pub fn on(&self, controls: &mut Controls, state: &mut State) {
+        for env in self.envelopes.iter() {
+            env.on(controls, state);
+        }
+    }
+
+    pub fn off(&self, controls: &mut Controls) {
+        for env in self.envelopes.iter() {
+            env.off(controls);
+        }
+    }
+}
+
+impl Signal for FmVoice {
+    tag!();
+
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let n = self.operators();
+        let hz = self.hz(controls, outputs);
+        let index = self
+            .algorithm(controls, outputs)
+            .clamp(1, self.algorithms.len());
+        let algorithm = self.algorithms[index - 1];
+        // State holds the phase, last output and the one before for each op.
+        let mut out = [0.0; MAX_OPERATORS];
+        for (op, o) in out.iter_mut().enumerate().take(n) {
+            *o = state[(self.tag, MAX_OPERATORS + op)];
+        }
+        let mut sum = 0.0;
+        let mut carriers = 0;
+        for op in (0..n).rev() {
+            let phase = state[(self.tag, op)];
+            let prev = state[(self.tag, MAX_OPERATORS + op)];
+            let fb = self.feedback(controls, outputs, op);
+            let mut pm = 0.0;
+            for (m, o) in out.iter().enumerate().take(n) {
+                if algorithm.modulates(m, op) {
+                    pm += o;
+                }
+                if algorithm.feeds_back(m, op) {
+                    let last = state[(self.tag, MAX_OPERATORS + m)];
+                    let last2 = state[(self.tag, 2 * MAX_OPERATORS + m)];
+                    pm += fb * 0.5 * (last + last2);
+                }
+            }
+            let env = outputs[(self.envelopes[op].tag(), 0)];
+            let y = self.level(controls, outputs, op) * env * (2.0 * PI * (phase + pm)).sin();
+            out[op] = y;
+            if algorithm.is_carrier(op) {
+                sum += y;
+                carriers += 1;
+            }
+            let fixed = self.fixed(controls, outputs, op);
+            let op_hz = if fixed != 0.0 {
+                fixed
+            } else {
+                self.ratio(controls, outputs, op) * hz
+            };
+            let ph = phase + op_hz / sample_rate;
+            state[(self.tag, op)] = ph - ph.floor();
+            state[(self.tag, 2 * MAX_OPERATORS + op)] = prev;
+            state[(self.tag, MAX_OPERATORS + op)] = y;
+        }
+        let amp = self.amplitude(controls, outputs);
+        outputs[(self.tag, 0)] = amp * sum / carriers.max(1) as f32;
+    }
+}
+
+#[derive(Clone)]
+pub struct FmVoiceBuilder {
+    operators: usize,
+    algorithms: Vec<Algorithm>,
+    hz: Control,
+    amplitude: Control,
+    algorithm: Control,
+    ratio: Vec<Control>,
+    level: Vec<Control>,
+    feedback: Vec<Control>,
+    fixed: Vec<Control>,
+}
+
+impl FmVoiceBuilder {
+    /// A voice with 4 or 6 operators, using the DX21 or DX7 algorithms.
+    pub fn new(operators: usize) -> Self {
+        let algorithms = match operators {
+            4 => (1..=8).map(Algorithm::four_op).collect(),
+            6 => (1..=32).map(Algorithm::dx7).collect(),
+            _ => panic!("An FM voice has 4 or 6 operators, not {}", operators),
+        };
+        Self {
+            operators,
+            algorithms,
+            hz: 0.0.into(),
+            amplitude: 1.0.into(),
+            algorithm: 1.into(),
+            ratio: vec![1.0.into(); operators],
+            level: vec![1.0.into(); operators],
+            feedback: vec![0.0.into(); operators],
+            fixed: vec![0.0.into(); operators],
+        }
+    }
+    /// Replace the default algorithms.
+    pub fn algorithms(&mut self, algorithms: Vec<Algorithm>) -> &mut Self {
+        self.algorithms = algorithms;
+        self
+    }
+    build!(hz);
+    build!(amplitude);
+    build!(algorithm);
+    pub fn ratio<T: Into<Control>>(&mut self, op: usize, value: T) -> &mut Self {
+        self.ratio[op] = value.into();
+        self
+    }
+    pub fn level<T: Into<Control>>(&mut self, op: usize, value: T) -> &mut Self {
+        self.level[op] = value.into();
+        self
+    }
+    pub fn feedback<T: Into<Control>>(&mut self, op: usize, value: T) -> &mut Self {
+        self.feedback[op] = value.into();
+        self
+    }
+    pub fn fixed<T: Into<Control>>(&mut self, op: usize, value: T) -> &mut Self {
+        self.fixed[op] = value.into();
+        self
+    }
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<FmVoice> {
+        let envelopes = (0..self.operators)
+            .map(|_| AdsrBuilder::new().rack(rack, controls))
+            .collect();
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.hz;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.algorithm;
+        for op in 0..self.operators {
+            controls[(n, 3 + 4 * op)] = self.ratio[op];
+            controls[(n, 3 + 4 * op + 1)] = self.level[op];
+            controls[(n, 3 + 4 * op + 2)] = self.feedback[op];
+            controls[(n, 3 + 4 * op + 3)] = self.fixed[op];
+        }
+        let voice = Arc::new(FmVoice::new(n, envelopes, self.algorithms.clone()));
+        rack.push(voice.clone());
+        voice
+    }
+}
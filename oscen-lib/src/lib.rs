@@ -22,14 +22,25 @@
 //! possible to know at compile time about a patch that will be added while the
 //! synth is running.
 //!
+//! ### Cargo features
+//! - **std** (default) - Without it oscen is `no_std` and only needs `alloc`,
+//!   so the same synth modules can run on a microcontroller.
+//! - **midi** (default) - Midi modules and reading midi input from the host.
+//! - **noise** (default) - White and pink noise from the `rand` crate.
+//! - **wav** (default) - Loading wavetables and samples from wav files.
+//!
 //! [`Signal`]: signal/trait.Signal.html
 //! [`Rack`]: signal/struct.Rack.html
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 /// Envelope generators.
 pub mod envelopes;
 /// A collection of some basic audio filters.
 pub mod filters;
 /// Midi interface nodes.
+#[cfg(feature = "midi")]
 pub mod midi;
 /// Syth modules for combining other sytn modules.
 pub mod operators;
@@ -37,6 +48,8 @@ pub mod operators;
 pub mod oscillators;
 /// Core Oscen types and traits.
 pub mod rack;
+/// Sample playback.
+pub mod sampler;
 /// An implementation of *freeverb*.
 // pub mod reverb;
 /// Wave shaping.
@@ -1,7 +1,11 @@
-use crate::oscillators::{ConstBuilder, OscBuilder};
+use crate::oscillators::{sinc, ConstBuilder, OscBuilder};
 use crate::rack::*;
+use crate::utils::random;
 use crate::{build, props, tag};
-use std::sync::Arc;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num::Float;
 #[derive(Debug, Clone)]
 pub struct Mixer {
     tag: Tag,
@@ -28,6 +32,32 @@ impl MixerBuilder {
         rack.push(mix.clone());
         mix
     }
+    /// Like `rack`, but any wave with less latency than the slowest one is
+    /// first sent through a `Delay` so that parallel paths line up.
+    pub fn rack_compensated(
+        &self,
+        rack: &mut Rack,
+        controls: &mut Controls,
+        outputs: &Outputs,
+        buffers: &mut Buffers,
+        sample_rate: f32,
+    ) -> Arc<Mixer> {
+        let latencies = rack.latencies(controls, outputs, buffers, sample_rate);
+        let latency = |w: &Tag| latencies[usize::from(*w)];
+        let max = self.waves.iter().map(latency).fold(0.0, f32::max);
+        let mut waves = vec![];
+        for w in self.waves.iter() {
+            let lag = max - latency(w);
+            if lag > 0.0 {
+                let delay =
+                    DelayBuilder::new(*w, (lag / sample_rate).into()).rack(rack, controls, buffers);
+                waves.push(delay.tag());
+            } else {
+                waves.push(*w);
+            }
+        }
+        MixerBuilder::new(waves).rack(rack, controls)
+    }
 }
 
 impl Mixer {
@@ -38,6 +68,13 @@ impl Mixer {
 
 impl Signal for Mixer {
     tag!();
+    fn inputs(&self, controls: &Controls) -> Vec<Tag> {
+        controls.controls(self.tag)[0..self.num_waves as usize]
+            .iter()
+            .map(|c| c.idx().into())
+            .collect()
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -103,6 +140,13 @@ impl Union {
 
 impl Signal for Union {
     tag!();
+    fn inputs(&self, controls: &Controls) -> Vec<Tag> {
+        controls.controls(self.tag)[1..=self.num_waves as usize]
+            .iter()
+            .map(|c| c.idx().into())
+            .collect()
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -154,6 +198,13 @@ impl Product {
 
 impl Signal for Product {
     tag!();
+    fn inputs(&self, controls: &Controls) -> Vec<Tag> {
+        controls.controls(self.tag)[0..self.num_waves as usize]
+            .iter()
+            .map(|c| c.idx().into())
+            .collect()
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -184,6 +235,9 @@ impl Inverse {
 
 impl Signal for Inverse {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
 
     fn signal(
         &self,
@@ -230,6 +284,10 @@ impl Vca {
 
 impl Signal for Vca {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -281,6 +339,10 @@ impl CrossFade {
 
 impl Signal for CrossFade {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave1, self.wave2]
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -320,6 +382,141 @@ impl CrossFadeBuilder {
     }
 }
 
+/// A modulation matrix. Each destination is a base value plus the sum of the
+/// sources weighted by their depths, destination `j` is written to output `j`
+/// so that it can drive the control of any module. Sources and bases are
+/// controls, the depths are kept in the module's buffer so there can be one
+/// for every pair.
+#[derive(Debug, Clone)]
+pub struct ModMatrix {
+    tag: Tag,
+    num_sources: u8,
+    num_destinations: u8,
+}
+
+impl ModMatrix {
+    pub fn new(tag: Tag, num_sources: u8, num_destinations: u8) -> Self {
+        Self {
+            tag,
+            num_sources,
+            num_destinations,
+        }
+    }
+    fn base_idx(&self, j: usize) -> usize {
+        self.num_sources as usize + j
+    }
+    fn depth_idx(&self, i: usize, j: usize) -> usize {
+        i * self.num_destinations as usize + j
+    }
+    pub fn source(&self, controls: &Controls, outputs: &Outputs, i: usize) -> f32 {
+        outputs.value(controls[(self.tag, i)]).unwrap()
+    }
+    pub fn set_source(&self, controls: &mut Controls, i: usize, value: Control) {
+        controls[(self.tag, i)] = value;
+    }
+    pub fn base(&self, controls: &Controls, outputs: &Outputs, j: usize) -> f32 {
+        outputs
+            .value(controls[(self.tag, self.base_idx(j))])
+            .unwrap()
+    }
+    pub fn set_base(&self, controls: &mut Controls, j: usize, value: Control) {
+        controls[(self.tag, self.base_idx(j))] = value;
+    }
+    pub fn depth(&self, buffers: &Buffers, i: usize, j: usize) -> f32 {
+        buffers.buffers(self.tag)[self.depth_idx(i, j)]
+    }
+    pub fn set_depth(&self, buffers: &mut Buffers, i: usize, j: usize, value: f32) {
+        buffers.buffers_mut(self.tag)[self.depth_idx(i, j)] = value;
+    }
+    /// The control to patch destination `j` into the input of another module.
+    pub fn destination(&self, j: usize) -> Control {
+        Control::V(self.tag, j)
+    }
+}
+
+impl Signal for ModMatrix {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        _state: &mut State,
+        outputs: &mut Outputs,
+        buffers: &mut Buffers,
+        _sample_rate: f32,
+    ) {
+        for j in 0..self.num_destinations as usize {
+            let mut out = self.base(controls, outputs, j);
+            for i in 0..self.num_sources as usize {
+                out += self.depth(buffers, i, j) * self.source(controls, outputs, i);
+            }
+            outputs[(self.tag, j)] = out;
+        }
+    }
+}
+
+/// Builds a `ModMatrix` with `sources.len()` sources and `num_destinations`
+/// destinations. Each source and each destination's base takes a control, so
+/// together there can be at most `MAX_CONTROLS` of them, and there are at most
+/// `MAX_OUTPUTS` destinations. The number of depths is not limited.
+#[derive(Debug, Clone)]
+pub struct ModMatrixBuilder {
+    sources: Vec<Tag>,
+    bases: Vec<Control>,
+    depths: Vec<Vec<f32>>,
+}
+
+impl ModMatrixBuilder {
+    pub fn new(sources: Vec<Tag>, num_destinations: usize) -> Self {
+        let ns = sources.len();
+        assert!(
+            ns + num_destinations <= MAX_CONTROLS,
+            "A mod matrix has at most {} sources and destinations together, not {}",
+            MAX_CONTROLS,
+            ns + num_destinations
+        );
+        assert!(
+            num_destinations <= MAX_OUTPUTS,
+            "A mod matrix has at most {} destinations, not {}",
+            MAX_OUTPUTS,
+            num_destinations
+        );
+        Self {
+            sources,
+            bases: vec![0.0.into(); num_destinations],
+            depths: vec![vec![0.0; num_destinations]; ns],
+        }
+    }
+    pub fn base<T: Into<Control>>(&mut self, j: usize, value: T) -> &mut Self {
+        self.bases[j] = value.into();
+        self
+    }
+    pub fn depth(&mut self, i: usize, j: usize, value: f32) -> &mut Self {
+        self.depths[i][j] = value;
+        self
+    }
+    pub fn rack(
+        &self,
+        rack: &mut Rack,
+        controls: &mut Controls,
+        buffers: &mut Buffers,
+    ) -> Arc<ModMatrix> {
+        let n = rack.num_modules();
+        let ns = self.sources.len() as u8;
+        let nd = self.bases.len() as u8;
+        let matrix = Arc::new(ModMatrix::new(n.into(), ns, nd));
+        let depths = self.depths.iter().flatten().copied().collect();
+        buffers.set_buffer(matrix.tag(), RingBuffer::new(0, depths));
+        for (i, s) in self.sources.iter().enumerate() {
+            matrix.set_source(controls, i, (*s).into());
+        }
+        for (j, b) in self.bases.iter().enumerate() {
+            matrix.set_base(controls, j, *b);
+        }
+        rack.push(matrix.clone());
+        matrix
+    }
+}
+
 #[derive(Clone)]
 pub struct Modulator {
     tag: Tag,
@@ -436,6 +633,19 @@ impl Delay {
 
 impl Signal for Delay {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+    fn latency(
+        &self,
+        controls: &Controls,
+        outputs: &Outputs,
+        _buffers: &Buffers,
+        sample_rate: f32,
+    ) -> f32 {
+        self.delay(controls, outputs) * sample_rate
+    }
+
     fn signal(
         &self,
         controls: &Controls,
@@ -477,3 +687,449 @@ impl DelayBuilder {
         delay
     }
 }
+
+/// Number of taps in each polyphase branch of the oversampling filters.
+const OVERSAMPLE_TAPS: usize = 8;
+
+/// The input of a sub-patch run by an `Oversampler`, its output is written by
+/// the `Oversampler` before each oversampled step.
+#[derive(Debug, Copy, Clone)]
+pub struct OversampleInput {
+    tag: Tag,
+}
+
+impl OversampleInput {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+}
+
+impl Signal for OversampleInput {
+    tag!();
+    fn signal(
+        &self,
+        _controls: &Controls,
+        _state: &mut State,
+        _outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        _sample_rate: f32,
+    ) {
+    }
+}
+
+/// Runs a sub-patch at `factor` times the sample rate. The input is
+/// interpolated and the output decimated with polyphase windowed sinc filters,
+/// so that nonlinear modules like `Tanh` and `SineFold` do not alias.
+#[derive(Clone)]
+pub struct Oversampler {
+    tag: Tag,
+    wave: Tag,
+    input: Tag,
+    output: Tag,
+    factor: usize,
+    kernel: Vec<f32>,
+    patch: Vec<Arc<dyn Signal + Send + Sync>>,
+}
+
+impl Oversampler {
+    pub fn new<T: Into<Tag>>(
+        tag: T,
+        wave: Tag,
+        input: Tag,
+        output: Tag,
+        factor: usize,
+        patch: Vec<Arc<dyn Signal + Send + Sync>>,
+    ) -> Self {
+        assert!(factor > 0, "Oversampling factor must be at least 1");
+        Self {
+            tag: tag.into(),
+            wave,
+            input,
+            output,
+            factor,
+            kernel: oversample_kernel(factor),
+            patch,
+        }
+    }
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+}
+
+/// Blackman windowed sinc lowpass with its cutoff just below the Nyquist
+/// frequency of the base rate, normalized to unity gain at DC.
+fn oversample_kernel(factor: usize) -> Vec<f32> {
+    let n = factor * OVERSAMPLE_TAPS;
+    let m = (n - 1) as f32;
+    let cutoff = 0.45 / factor as f32;
+    let mut kernel: Vec<f32> = (0..n)
+        .map(|i| {
+            let x = i as f32;
+            let window = 0.42 - 0.5 * (2.0 * PI * x / m).cos() + 0.08 * (4.0 * PI * x / m).cos();
+            sinc(2.0 * cutoff * (x - m / 2.0)) * window
+        })
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    for k in kernel.iter_mut() {
+        *k /= sum;
+    }
+    kernel
+}
+
+impl Signal for Oversampler {
+    tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
+    /// The delay of the interpolation and decimation filters plus that of the
+    /// sub-patch, both measured at the base rate.
+    fn latency(
+        &self,
+        controls: &Controls,
+        outputs: &Outputs,
+        buffers: &Buffers,
+        sample_rate: f32,
+    ) -> f32 {
+        let factor = self.factor as f32;
+        let patch = path_latencies(
+            &self.patch,
+            controls,
+            outputs,
+            buffers,
+            sample_rate * factor,
+        );
+        ((self.kernel.len() - 1) as f32 + patch[usize::from(self.output)]) / factor
+    }
+
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let factor = self.factor;
+        buffers
+            .buffers_mut(self.input)
+            .push(outputs[(self.wave, 0)]);
+        for phase in 0..factor {
+            let history = buffers.buffers(self.input);
+            let x = (0..OVERSAMPLE_TAPS).fold(0.0, |acc, j| {
+                acc + self.kernel[j * factor + phase] * history.get(j as f32)
+            });
+            outputs[(self.input, 0)] = x * factor as f32;
+            for module in self.patch.iter() {
+                module.signal(
+                    controls,
+                    state,
+                    outputs,
+                    buffers,
+                    sample_rate * factor as f32,
+                );
+            }
+            let y = outputs[(self.output, 0)];
+            buffers.buffers_mut(self.tag).push(y);
+        }
+        let history = buffers.buffers(self.tag);
+        outputs[(self.tag, 0)] = self
+            .kernel
+            .iter()
+            .enumerate()
+            .fold(0.0, |acc, (i, k)| acc + k * history.get(i as f32));
+    }
+}
+
+/// Wraps the modules racked between `input` and `rack` in an `Oversampler`.
+/// ```
+/// use oscen::operators::OversamplerBuilder;
+/// use oscen::oscillators::{sine_osc, OscBuilder};
+/// use oscen::rack::*;
+/// use oscen::shaping::TanhBuilder;
+///
+/// let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+/// let osc = OscBuilder::new(sine_osc)
+///     .hz(440.0)
+///     .rack(&mut rack, &mut controls, &mut state);
+/// let mut builder = OversamplerBuilder::new(osc.tag(), 4);
+/// let input = builder.input(&mut rack, &mut buffers);
+/// let tanh = TanhBuilder::new(input.tag()).rack(&mut rack);
+/// builder.rack(&mut rack, &mut buffers, tanh.tag());
+/// rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100.0);
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct OversamplerBuilder {
+    wave: Tag,
+    factor: usize,
+    input: Option<Tag>,
+}
+
+impl OversamplerBuilder {
+    pub fn new(wave: Tag, factor: usize) -> Self {
+        Self {
+            wave,
+            factor,
+            input: None,
+        }
+    }
+
+    /// Rack the input of the sub-patch, every module racked after it until
+    /// `rack` is called will run at the oversampled rate.
+    pub fn input(&mut self, rack: &mut Rack, buffers: &mut Buffers) -> Arc<OversampleInput> {
+        let n = rack.num_modules();
+        let input = Arc::new(OversampleInput::new(n));
+        buffers.set_buffer(input.tag(), RingBuffer::new(0, vec![0.0; OVERSAMPLE_TAPS]));
+        rack.push(input.clone());
+        self.input = Some(input.tag());
+        input
+    }
+
+    pub fn rack(&self, rack: &mut Rack, buffers: &mut Buffers, output: Tag) -> Arc<Oversampler> {
+        let input = self
+            .input
+            .expect("OversamplerBuilder::input must be called before rack");
+        let patch = rack.detach(input.into());
+        let n = rack.num_modules();
+        let os = Arc::new(Oversampler::new(
+            n,
+            self.wave,
+            input,
+            output,
+            self.factor,
+            patch,
+        ));
+        buffers.set_buffer(
+            os.tag(),
+            RingBuffer::new(0, vec![0.0; self.factor * OVERSAMPLE_TAPS]),
+        );
+        rack.push(os.clone());
+        os
+    }
+}
+
+/// True on a rising edge of a pulse input, keeping the last value in `slot`.
+fn rising(state: &mut State, tag: Tag, slot: usize, value: f32) -> bool {
+    let edge = state[(tag, slot)] <= 0.0 && value > 0.0;
+    state[(tag, slot)] = value;
+    edge
+}
+
+/// Emits a pulse on every `division`th rising edge of `pulse`, `shift` moves
+/// which of the edges in each group passes.
+#[derive(Clone)]
+pub struct ClockDivider {
+    tag: Tag,
+}
+
+#[derive(Clone)]
+pub struct ClockDividerBuilder {
+    pulse: Control,
+    division: Control,
+    shift: Control,
+}
+
+impl ClockDivider {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(pulse, set_pulse, 0);
+    props!(division, set_division, 1);
+    props!(shift, set_shift, 2);
+}
+
+impl ClockDividerBuilder {
+    pub fn new<T: Into<Control>>(pulse: T) -> Self {
+        Self {
+            pulse: pulse.into(),
+            division: 2.0.into(),
+            shift: 0.0.into(),
+        }
+    }
+    build!(division);
+    build!(shift);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<ClockDivider> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.pulse;
+        controls[(n, 1)] = self.division;
+        controls[(n, 2)] = self.shift;
+        let divider = Arc::new(ClockDivider::new(n));
+        rack.push(divider.clone());
+        divider
+    }
+}
+
+impl Signal for ClockDivider {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        _sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let pulse = self.pulse(controls, outputs);
+        let mut out = 0.0;
+        if rising(state, tag, 0, pulse) {
+            let division = self.division(controls, outputs).round().max(1.0) as i64;
+            let count = state[(tag, 1)] as i64 - self.shift(controls, outputs).round() as i64;
+            if count.rem_euclid(division) == 0 {
+                out = 1.0;
+            }
+            state[(tag, 1)] += 1.0;
+        }
+        outputs[(tag, 0)] = out;
+    }
+}
+
+/// A clock follower that measures the period between rising edges of `pulse`
+/// and emits `factor` evenly spaced pulses per period, locked to the input
+/// edges. `shift` delays the pulses by a fraction of the output period.
+/// Until two input edges have arrived the input pulses are passed through.
+#[derive(Clone)]
+pub struct ClockMultiplier {
+    tag: Tag,
+}
+
+#[derive(Clone)]
+pub struct ClockMultiplierBuilder {
+    pulse: Control,
+    factor: Control,
+    shift: Control,
+}
+
+impl ClockMultiplier {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(pulse, set_pulse, 0);
+    props!(factor, set_factor, 1);
+    props!(shift, set_shift, 2);
+
+    /// The measured period of the input in samples, 0 if not yet known.
+    pub fn period(&self, state: &State) -> f32 {
+        state[(self.tag, 2)]
+    }
+}
+
+impl ClockMultiplierBuilder {
+    pub fn new<T: Into<Control>>(pulse: T) -> Self {
+        Self {
+            pulse: pulse.into(),
+            factor: 2.0.into(),
+            shift: 0.0.into(),
+        }
+    }
+    build!(factor);
+    build!(shift);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<ClockMultiplier> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.pulse;
+        controls[(n, 1)] = self.factor;
+        controls[(n, 2)] = self.shift;
+        let multiplier = Arc::new(ClockMultiplier::new(n));
+        rack.push(multiplier.clone());
+        multiplier
+    }
+}
+
+impl Signal for ClockMultiplier {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        _sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        // Slots: 0 input, 1 samples since the last edge, 2 period, 3 the
+        // previous position in output periods and 4 is set after an edge.
+        let pulse = self.pulse(controls, outputs);
+        let factor = self.factor(controls, outputs).round().max(1.0);
+        let shift = self.shift(controls, outputs);
+        let edge = rising(state, tag, 0, pulse);
+        if edge {
+            if state[(tag, 4)] != 0.0 {
+                state[(tag, 2)] = state[(tag, 1)];
+            }
+            state[(tag, 1)] = 0.0;
+            state[(tag, 3)] = -shift - 1e-6;
+            state[(tag, 4)] = 1.0;
+        }
+        let period = self.period(state);
+        let out = if period > 0.0 {
+            let x = state[(tag, 1)] / period * factor - shift;
+            let pulse = x.floor() > state[(tag, 3)].floor() && x.floor() < factor;
+            state[(tag, 3)] = x;
+            pulse
+        } else {
+            edge
+        };
+        state[(tag, 1)] += 1.0;
+        outputs[(tag, 0)] = out as i32 as f32;
+    }
+}
+
+/// Passes the rising edges of `pulse` on, skipping each with `probability`.
+/// The choice is pseudo random and repeatable.
+#[derive(Clone)]
+pub struct ClockSkip {
+    tag: Tag,
+}
+
+#[derive(Clone)]
+pub struct ClockSkipBuilder {
+    pulse: Control,
+    probability: Control,
+}
+
+impl ClockSkip {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(pulse, set_pulse, 0);
+    props!(probability, set_probability, 1);
+}
+
+impl ClockSkipBuilder {
+    pub fn new<T: Into<Control>>(pulse: T) -> Self {
+        Self {
+            pulse: pulse.into(),
+            probability: 0.5.into(),
+        }
+    }
+    build!(probability);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<ClockSkip> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.pulse;
+        controls[(n, 1)] = self.probability;
+        let skip = Arc::new(ClockSkip::new(n));
+        rack.push(skip.clone());
+        skip
+    }
+}
+
+impl Signal for ClockSkip {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        _sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let pulse = self.pulse(controls, outputs);
+        let mut out = 0.0;
+        if rising(state, tag, 0, pulse)
+            && random(state, tag, 1) >= self.probability(controls, outputs)
+        {
+            out = 1.0;
+        }
+        outputs[(tag, 0)] = out;
+    }
+}
@@ -1,37 +1,84 @@
 use crate::rack::*;
+#[cfg(feature = "wav")]
+use crate::utils::read_wav;
+use crate::utils::{fft, random};
 use crate::{build, props, tag};
-use math::round::floor;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::f32::consts;
+#[cfg(not(feature = "std"))]
+use num::Float;
+#[cfg(feature = "noise")]
 use rand::prelude::*;
+#[cfg(feature = "noise")]
 use rand_distr::{StandardNormal, Uniform};
-use std::f32::consts;
-use std::sync::Arc;
+#[cfg(feature = "wav")]
+use std::path::Path;
 
 const TAU: f32 = 2.0 * consts::PI;
 
+/// A signal function of the phase, an extra argument and the phase increment
+/// per sample, which band-limited waveforms need to smooth discontinuities.
+pub type BandLimitedFn = fn(f32, f32, f32) -> f32;
+
+/// The function an `Oscillator` uses to turn its phase into a signal.
+#[derive(Copy, Clone)]
+pub enum Waveform {
+    Naive(SignalFn),
+    BandLimited(BandLimitedFn),
+}
+
+impl Waveform {
+    pub fn sample(&self, phase: f32, arg: f32, dt: f32) -> f32 {
+        match self {
+            Waveform::Naive(f) => f(phase, arg),
+            Waveform::BandLimited(f) => f(phase, arg, dt),
+        }
+    }
+}
+
 pub struct OscBuilder {
-    signal_fn: fn(f32, f32) -> f32,
+    waveform: Waveform,
     phase: f32,
     hz: Control,
     amplitude: Control,
     arg: Control,
+    sync: Control,
+    reset: Control,
+    pm: Control,
+    fm: Control,
 }
 
 /// A standard oscillator that has phase, hz, and amp. Pass in a signal function
-/// to operate on the phase and an optional extra argument.
+/// to operate on the phase and an optional extra argument. The phase restarts
+/// when the `sync` or `reset` input crosses zero going up, `sync` with
+/// sub-sample accuracy for hard sync at audio rates. `pm` is added to the phase
+/// (in cycles) and `fm` to the frequency, which may go negative for
+/// through-zero FM.
 #[derive(Clone)]
 pub struct Oscillator {
     tag: Tag,
-    signal_fn: fn(f32, f32) -> f32,
+    waveform: Waveform,
 }
 
 impl OscBuilder {
     pub fn new(signal_fn: fn(f32, f32) -> f32) -> Self {
+        Self::with_waveform(Waveform::Naive(signal_fn))
+    }
+    /// An oscillator with a band-limited signal function like `saw_blep`.
+    pub fn band_limited(signal_fn: BandLimitedFn) -> Self {
+        Self::with_waveform(Waveform::BandLimited(signal_fn))
+    }
+    pub fn with_waveform(waveform: Waveform) -> Self {
         Self {
-            signal_fn,
+            waveform,
             phase: 0.0,
             hz: 0.0.into(),
             amplitude: 1.0.into(),
             arg: 0.5.into(),
+            sync: 0.0.into(),
+            reset: 0.0.into(),
+            pm: 0.0.into(),
+            fm: 0.0.into(),
         }
     }
     pub fn phase(&mut self, value: f32) -> &mut Self {
@@ -41,6 +88,10 @@ impl OscBuilder {
     build!(hz);
     build!(amplitude);
     build!(arg);
+    build!(sync);
+    build!(reset);
+    build!(pm);
+    build!(fm);
 
     pub fn rack(
         &self,
@@ -52,8 +103,12 @@ impl OscBuilder {
         controls[(n, 0)] = self.hz;
         controls[(n, 1)] = self.amplitude;
         controls[(n, 2)] = self.arg;
+        controls[(n, 3)] = self.sync;
+        controls[(n, 4)] = self.reset;
+        controls[(n, 5)] = self.pm;
+        controls[(n, 6)] = self.fm;
         state[(n, 0)] = self.phase;
-        let osc = Arc::new(Oscillator::new(n, self.signal_fn));
+        let osc = Arc::new(Oscillator::with_waveform(n, self.waveform));
         rack.push(osc.clone());
         osc
     }
@@ -74,7 +129,7 @@ pub fn square_osc(phase: f32, duty_cycle: f32) -> f32 {
 
 pub fn saw_osc(phase: f32, _: f32) -> f32 {
     let t = phase - 0.5;
-    let s = -t - floor(0.5 - t as f64, 0) as f32;
+    let s = -t - (0.5 - t).floor();
     if s < -0.5 {
         0.0
     } else {
@@ -84,15 +139,71 @@ pub fn saw_osc(phase: f32, _: f32) -> f32 {
 
 pub fn triangle_osc(phase: f32, _: f32) -> f32 {
     let t = phase - 0.75;
-    let saw_amp = 2. * (-t - floor(0.5 - t as f64, 0) as f32);
+    let saw_amp = 2. * (-t - (0.5 - t).floor());
     2.0 * saw_amp.abs() - 1.0
 }
 
+/// Polynomial approximation of the residual of a band-limited step of height 2
+/// at phase 0, `t` is the phase in [0, 1) and `dt` the phase increment.
+pub fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// The integral of `poly_blep`, the residual of a band-limited change of slope
+/// at phase 0.
+pub fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -x * x * x / 3.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 3.0
+    } else {
+        0.0
+    }
+}
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+/// PolyBLEP version of `square_osc`.
+pub fn square_blep(phase: f32, duty_cycle: f32, dt: f32) -> f32 {
+    let t = fract(phase);
+    let dt = dt.abs();
+    square_osc(t, duty_cycle) + poly_blep(t, dt) - poly_blep(fract(t - duty_cycle), dt)
+}
+
+/// PolyBLEP version of `saw_osc`.
+pub fn saw_blep(phase: f32, _: f32, dt: f32) -> f32 {
+    let t = fract(phase);
+    1.0 - 2.0 * t + poly_blep(t, dt.abs())
+}
+
+/// PolyBLAMP version of `triangle_osc`.
+pub fn triangle_blep(phase: f32, _: f32, dt: f32) -> f32 {
+    let t = fract(phase);
+    let dt = dt.abs();
+    triangle_osc(t, 0.0) - 4.0 * dt * poly_blamp(fract(t - 0.25), dt)
+        + 4.0 * dt * poly_blamp(fract(t - 0.75), dt)
+}
+
 impl Oscillator {
     pub fn new<T: Into<Tag>>(tag: T, signal_fn: fn(f32, f32) -> f32) -> Self {
+        Self::with_waveform(tag, Waveform::Naive(signal_fn))
+    }
+    pub fn with_waveform<T: Into<Tag>>(tag: T, waveform: Waveform) -> Self {
         Self {
             tag: tag.into(),
-            signal_fn,
+            waveform,
         }
     }
     pub fn phase(&self, state: &State) -> f32 {
@@ -104,6 +215,10 @@ impl Oscillator {
     props!(hz, set_hz, 0);
     props!(amplitude, set_amplitude, 1);
     props!(arg, set_arg, 2);
+    props!(sync, set_sync, 3);
+    props!(reset, set_reset, 4);
+    props!(pm, set_pm, 5);
+    props!(fm, set_fm, 6);
 }
 
 impl Signal for Oscillator {
@@ -120,15 +235,24 @@ impl Signal for Oscillator {
         let hz = self.hz(controls, outputs);
         let amp = self.amplitude(controls, outputs);
         let arg = self.arg(controls, outputs);
-        let mut ph = phase + hz / sample_rate;
-        while ph >= 1.0 {
-            ph -= 1.0
-        }
-        while ph <= -1.0 {
-            ph += 1.0
+        let dt = (hz + self.fm(controls, outputs)) / sample_rate;
+        let pm = self.pm(controls, outputs);
+        let sync = self.sync(controls, outputs);
+        let reset = self.reset(controls, outputs);
+        let prev_sync = state[(self.tag, 1)];
+        let prev_reset = state[(self.tag, 2)];
+        state[(self.tag, 1)] = sync;
+        state[(self.tag, 2)] = reset;
+        let mut phase = phase;
+        if prev_reset <= 0.0 && reset > 0.0 {
+            phase = 0.0;
+        } else if prev_sync <= 0.0 && sync > 0.0 {
+            // Fraction of the sample period that has passed since the crossing.
+            let since = sync / (sync - prev_sync);
+            phase = since * dt;
         }
-        self.set_phase(state, ph);
-        outputs[(self.tag, 0)] = amp * (self.signal_fn)(phase, arg);
+        self.set_phase(state, fract(phase + dt));
+        outputs[(self.tag, 0)] = amp * self.waveform.sample(fract(phase + pm), arg, dt);
     }
 }
 
@@ -178,12 +302,14 @@ impl Signal for Const {
     }
 }
 
+#[cfg(feature = "noise")]
 #[derive(Copy, Clone)]
 pub enum NoiseDistribution {
     StdNormal,
     Uni,
 }
 
+#[cfg(feature = "noise")]
 /// White noise oscillator.
 #[derive(Copy, Clone)]
 pub struct WhiteNoise {
@@ -191,12 +317,14 @@ pub struct WhiteNoise {
     dist: NoiseDistribution,
 }
 
+#[cfg(feature = "noise")]
 #[derive(Copy, Clone)]
 pub struct WhiteNoiseBuilder {
     amplitude: Control,
     dist: NoiseDistribution,
 }
 
+#[cfg(feature = "noise")]
 impl WhiteNoiseBuilder {
     pub fn new() -> Self {
         Self {
@@ -218,6 +346,7 @@ impl WhiteNoiseBuilder {
     }
 }
 
+#[cfg(feature = "noise")]
 impl WhiteNoise {
     pub fn new<T: Into<Tag>>(tag: T, dist: NoiseDistribution) -> Self {
         Self {
@@ -228,6 +357,7 @@ impl WhiteNoise {
     props!(amplitude, set_amplitude, 0);
 }
 
+#[cfg(feature = "noise")]
 impl Signal for WhiteNoise {
     tag!();
     fn signal(
@@ -251,16 +381,19 @@ impl Signal for WhiteNoise {
     }
 }
 
+#[cfg(feature = "noise")]
 #[derive(Copy, Clone)]
 pub struct PinkNoise {
     tag: Tag,
 }
 
+#[cfg(feature = "noise")]
 #[derive(Copy, Clone)]
 pub struct PinkNoiseBuilder {
     amplitude: Control,
 }
 
+#[cfg(feature = "noise")]
 impl PinkNoise {
     pub fn new<T: Into<Tag>>(tag: T) -> Self {
         Self { tag: tag.into() }
@@ -268,6 +401,7 @@ impl PinkNoise {
     props!(amplitude, set_amplitude, 0);
 }
 
+#[cfg(feature = "noise")]
 impl PinkNoiseBuilder {
     pub fn new() -> Self {
         Self {
@@ -284,6 +418,7 @@ impl PinkNoiseBuilder {
     }
 }
 
+#[cfg(feature = "noise")]
 impl Signal for PinkNoise {
     tag!();
     fn signal(
@@ -294,26 +429,306 @@ impl Signal for PinkNoise {
         _buffers: &mut Buffers,
         _sample_rate: f32,
     ) {
-        let tag = self.tag;
         let amplitude = self.amplitude(controls, outputs);
         let mut rng = thread_rng();
         let white = Uniform::new_inclusive(-1.0, 1.0).sample(&mut rng);
-        state[(tag, 0)] = 0.99886 * state[(tag, 0)] + white * 0.0555179;
-        state[(tag, 1)] = 0.99332 * state[(tag, 1)] + white * 0.0750759;
-        state[(tag, 2)] = 0.96900 * state[(tag, 2)] + white * 0.1538520;
-        state[(tag, 3)] = 0.86650 * state[(tag, 3)] + white * 0.3104856;
-        state[(tag, 4)] = 0.55000 * state[(tag, 4)] + white * 0.5329522;
-        state[(tag, 5)] = -0.7616 * state[(tag, 5)] - white * 0.0168980;
-        let pink = state[(tag, 0)]
-            + state[(tag, 1)]
-            + state[(tag, 2)]
-            + state[(tag, 3)]
-            + state[(tag, 4)]
-            + state[(tag, 5)]
-            + state[(tag, 6)]
-            + white * 0.5362;
-        state[(tag, 6)] = white * 0.115926;
-        outputs[(self.tag, 0)] = pink * amplitude;
+        outputs[(self.tag, 0)] = pink_filter(state, self.tag, white) * amplitude;
+    }
+}
+
+/// Paul Kellet's pink noise filter using state slots 0 to 6.
+fn pink_filter(state: &mut State, tag: Tag, white: f32) -> f32 {
+    state[(tag, 0)] = 0.99886 * state[(tag, 0)] + white * 0.0555179;
+    state[(tag, 1)] = 0.99332 * state[(tag, 1)] + white * 0.0750759;
+    state[(tag, 2)] = 0.96900 * state[(tag, 2)] + white * 0.1538520;
+    state[(tag, 3)] = 0.86650 * state[(tag, 3)] + white * 0.3104856;
+    state[(tag, 4)] = 0.55000 * state[(tag, 4)] + white * 0.5329522;
+    state[(tag, 5)] = -0.7616 * state[(tag, 5)] - white * 0.0168980;
+    let pink = state[(tag, 0)]
+        + state[(tag, 1)]
+        + state[(tag, 2)]
+        + state[(tag, 3)]
+        + state[(tag, 4)]
+        + state[(tag, 5)]
+        + state[(tag, 6)]
+        + white * 0.5362;
+    state[(tag, 6)] = white * 0.115926;
+    pink
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoiseColor {
+    /// -6 dB per octave, also called red noise.
+    Brown,
+    /// +3 dB per octave.
+    Blue,
+    /// +6 dB per octave.
+    Violet,
+}
+
+/// Brown, blue or violet noise. Brown noise is leaky integrated white noise,
+/// blue and violet noise are differentiated pink and white noise.
+#[derive(Copy, Clone)]
+pub struct ColoredNoise {
+    tag: Tag,
+    color: NoiseColor,
+}
+
+#[derive(Copy, Clone)]
+pub struct ColoredNoiseBuilder {
+    amplitude: Control,
+    color: NoiseColor,
+}
+
+impl ColoredNoise {
+    pub fn new<T: Into<Tag>>(tag: T, color: NoiseColor) -> Self {
+        Self {
+            tag: tag.into(),
+            color,
+        }
+    }
+    props!(amplitude, set_amplitude, 0);
+}
+
+impl ColoredNoiseBuilder {
+    pub fn new(color: NoiseColor) -> Self {
+        Self {
+            amplitude: 1.0.into(),
+            color,
+        }
+    }
+    build!(amplitude);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<ColoredNoise> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.amplitude;
+        let noise = Arc::new(ColoredNoise::new(n, self.color));
+        rack.push(noise.clone());
+        noise
+    }
+}
+
+impl Signal for ColoredNoise {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        _sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let amplitude = self.amplitude(controls, outputs);
+        // The previous value is kept in slot 7, after the pink filter, and the
+        // generator in slot 8.
+        let white = 2.0 * random(state, tag, 8) - 1.0;
+        let out = match self.color {
+            NoiseColor::Brown => {
+                let brown = (state[(tag, 7)] + 0.02 * white) / 1.02;
+                state[(tag, 7)] = brown;
+                3.5 * brown
+            }
+            NoiseColor::Blue => {
+                let pink = pink_filter(state, tag, white) * 0.25;
+                let blue = pink - state[(tag, 7)];
+                state[(tag, 7)] = pink;
+                blue
+            }
+            NoiseColor::Violet => {
+                let violet = 0.5 * (white - state[(tag, 7)]);
+                state[(tag, 7)] = white;
+                violet
+            }
+        };
+        outputs[(tag, 0)] = out * amplitude;
+    }
+}
+
+/// Velvet noise, one impulse of random sign at a random position in each
+/// period of `1 / density` seconds and silence elsewhere.
+#[derive(Copy, Clone)]
+pub struct VelvetNoise {
+    tag: Tag,
+}
+
+#[derive(Copy, Clone)]
+pub struct VelvetNoiseBuilder {
+    density: Control,
+    amplitude: Control,
+}
+
+impl VelvetNoise {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(density, set_density, 0);
+    props!(amplitude, set_amplitude, 1);
+}
+
+impl VelvetNoiseBuilder {
+    pub fn new() -> Self {
+        Self {
+            density: 2000.0.into(),
+            amplitude: 1.0.into(),
+        }
+    }
+    build!(density);
+    build!(amplitude);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<VelvetNoise> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.density;
+        controls[(n, 1)] = self.amplitude;
+        let noise = Arc::new(VelvetNoise::new(n));
+        rack.push(noise.clone());
+        noise
+    }
+}
+
+impl Default for VelvetNoiseBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signal for VelvetNoise {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let period = (sample_rate / self.density(controls, outputs)).max(1.0);
+        // Slot 0 is the time into the period, 1 the impulse time, 2 its sign
+        // and 3 the generator.
+        if state[(tag, 0)] >= period || state[(tag, 0)] == 0.0 {
+            state[(tag, 0)] = 0.0;
+            state[(tag, 1)] = (random(state, tag, 3) * (period - 1.0)).round();
+            state[(tag, 2)] = if random(state, tag, 3) < 0.5 {
+                1.0
+            } else {
+                -1.0
+            };
+        }
+        let t = state[(tag, 0)];
+        outputs[(tag, 0)] = if t == state[(tag, 1)] {
+            state[(tag, 2)] * self.amplitude(controls, outputs)
+        } else {
+            0.0
+        };
+        state[(tag, 0)] = t + 1.0;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RandomMode {
+    /// A new random value every `1 / rate` seconds.
+    SampleHold,
+    /// Like `SampleHold` but gliding to each new value.
+    Smooth,
+    /// Brownian motion between -1 and 1, `rate` sets how fast it wanders.
+    Walk,
+}
+
+/// A random modulation source between `-amplitude` and `amplitude`.
+#[derive(Copy, Clone)]
+pub struct Random {
+    tag: Tag,
+    mode: RandomMode,
+}
+
+#[derive(Copy, Clone)]
+pub struct RandomBuilder {
+    rate: Control,
+    amplitude: Control,
+    mode: RandomMode,
+}
+
+impl Random {
+    pub fn new<T: Into<Tag>>(tag: T, mode: RandomMode) -> Self {
+        Self {
+            tag: tag.into(),
+            mode,
+        }
+    }
+    props!(rate, set_rate, 0);
+    props!(amplitude, set_amplitude, 1);
+}
+
+impl RandomBuilder {
+    pub fn new(mode: RandomMode) -> Self {
+        Self {
+            rate: 1.0.into(),
+            amplitude: 1.0.into(),
+            mode,
+        }
+    }
+    build!(rate);
+    build!(amplitude);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Random> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.rate;
+        controls[(n, 1)] = self.amplitude;
+        let random = Arc::new(Random::new(n, self.mode));
+        rack.push(random.clone());
+        random
+    }
+}
+
+impl Signal for Random {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let rate = self.rate(controls, outputs);
+        // Slot 0 is the phase, 1 the previous value, 2 the current value, 3
+        // flags the first value drawn and 4 is the generator.
+        let value = match self.mode {
+            RandomMode::Walk => {
+                // A sum of four uniform draws scaled to unit variance is close
+                // enough to a normal step.
+                let sum: f32 = (0..4).map(|_| random(state, tag, 4)).sum();
+                let step = (sum - 2.0) * 3f32.sqrt();
+                let mut v = state[(tag, 2)] + step * (rate / sample_rate).sqrt();
+                if v > 1.0 {
+                    v = 2.0 - v;
+                } else if v < -1.0 {
+                    v = -2.0 - v;
+                }
+                state[(tag, 2)] = v.clamp(-1.0, 1.0);
+                state[(tag, 2)]
+            }
+            mode => {
+                let phase = state[(tag, 0)];
+                if state[(tag, 3)] == 0.0 {
+                    state[(tag, 3)] = 1.0;
+                    state[(tag, 2)] = 2.0 * random(state, tag, 4) - 1.0;
+                }
+                let value = if mode == RandomMode::Smooth {
+                    let x = 0.5 - 0.5 * (consts::PI * phase).cos();
+                    state[(tag, 1)] + x * (state[(tag, 2)] - state[(tag, 1)])
+                } else {
+                    state[(tag, 2)]
+                };
+                let ph = phase + rate / sample_rate;
+                if ph >= 1.0 {
+                    state[(tag, 1)] = state[(tag, 2)];
+                    state[(tag, 2)] = 2.0 * random(state, tag, 4) - 1.0;
+                }
+                state[(tag, 0)] = ph - ph.floor();
+                value
+            }
+        };
+        outputs[(tag, 0)] = value * self.amplitude(controls, outputs);
     }
 }
 
@@ -376,7 +791,7 @@ impl FourierOscBuilder {
     }
 }
 
-fn sinc(x: f32) -> f32 {
+pub(crate) fn sinc(x: f32) -> f32 {
     if x == 0.0 {
         return 1.0;
     }
@@ -398,81 +813,91 @@ impl Signal for FourierOsc {
         let sigma = self.lanczos as i32;
         let mut out = 0.0;
         for (i, c) in self.coefficients.iter().enumerate() {
-            out += c
-                * sinc(sigma as f32 * i as f32 / self.coefficients.len() as f32)
-                * (state[(tag, i)] * TAU).sin();
-            state[(tag, i)] += hz * i as f32 / sample_rate;
-            while state[(tag, i)] >= 1.0 {
-                state[(tag, i)] -= 1.0;
-            }
-            while state[(tag, i)] <= -1.0 {
-                state[(tag, i)] += 1.0;
+            // Partials above Nyquist are muted but keep their phase running, so
+            // they come back in phase when the pitch drops.
+            if (hz * i as f32).abs() < sample_rate / 2.0 {
+                out += c
+                    * sinc(sigma as f32 * i as f32 / self.coefficients.len() as f32)
+                    * (state[(tag, i)] * TAU).sin();
             }
+            let ph = state[(tag, i)] + hz * i as f32 / sample_rate;
+            state[(tag, i)] = ph - ph.trunc();
         }
         outputs[(self.tag, 0)] = out * self.amplitude(controls, outputs);
     }
 }
 
-pub fn square_wave(n: u32) -> FourierOscBuilder {
-    let mut coefficients: Vec<f32> = Vec::new();
-    for i in 0..=n {
-        if i % 2 == 1 {
-            coefficients.push(1. / i as f32);
-        } else {
-            coefficients.push(0.);
-        }
-    }
-    FourierOscBuilder::new(coefficients)
-}
-
-pub fn triangle_wave(n: u32) -> FourierOscBuilder {
-    let mut coefficients: Vec<f32> = Vec::new();
-    for i in 0..=n {
-        if i % 2 == 1 {
-            let sgn = if i % 4 == 1 { -1.0 } else { 1.0 };
-            coefficients.push(sgn / (i * i) as f32);
-        } else {
-            coefficients.push(0.0);
-        }
-    }
-    FourierOscBuilder::new(coefficients)
-}
+/// Maximum number of partials of an `Additive` oscillator.
+pub const MAX_PARTIALS: usize = MAX_CONTROLS - 2;
 
-/// A `SynthModule` that emits 1.0 every `interval` seconds otherwise it emits
-/// 0.0.
-#[derive(Copy, Clone)]
-pub struct Clock {
+/// An additive oscillator whose partial amplitudes are controls, so they can
+/// be modulated. The sines of the harmonics are computed with the recurrence
+/// `sin((k + 1)x) = 2cos(x)sin(kx) - sin((k - 1)x)` and partials at or above
+/// the Nyquist frequency are skipped.
+#[derive(Clone)]
+pub struct Additive {
     tag: Tag,
+    partials: usize,
 }
 
-#[derive(Copy, Clone)]
-pub struct ClockBuilder {
-    interval: Control,
+#[derive(Clone)]
+pub struct AdditiveBuilder {
+    hz: Control,
+    amplitude: Control,
+    partials: Vec<Control>,
 }
 
-impl ClockBuilder {
-    pub fn new<T: Into<Control>>(interval: T) -> Self {
+impl Additive {
+    pub fn new<T: Into<Tag>>(tag: T, partials: usize) -> Self {
+        assert!(
+            partials <= MAX_PARTIALS,
+            "Max number of partials is {}",
+            MAX_PARTIALS
+        );
         Self {
-            interval: interval.into(),
+            tag: tag.into(),
+            partials,
         }
     }
-    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Clock> {
-        let n = rack.num_modules();
-        controls[(n, 0)] = self.interval;
-        let clock = Arc::new(Clock::new(n));
-        rack.push(clock.clone());
-        clock
+    props!(hz, set_hz, 0);
+    props!(amplitude, set_amplitude, 1);
+    pub fn partials(&self) -> usize {
+        self.partials
+    }
+    /// The amplitude of harmonic `k`, starting from 1 for the fundamental.
+    pub fn partial(&self, controls: &Controls, outputs: &Outputs, k: usize) -> f32 {
+        let inp = controls[(self.tag, k + 1)];
+        outputs.value(inp).unwrap()
+    }
+    pub fn set_partial(&self, controls: &mut Controls, k: usize, value: Control) {
+        controls[(self.tag, k + 1)] = value;
     }
 }
 
-impl Clock {
-    pub fn new<T: Into<Tag>>(tag: T) -> Self {
-        Self { tag: tag.into() }
+impl AdditiveBuilder {
+    pub fn new(partials: Vec<Control>) -> Self {
+        Self {
+            hz: 0.0.into(),
+            amplitude: 1.0.into(),
+            partials,
+        }
+    }
+    build!(hz);
+    build!(amplitude);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Additive> {
+        let n = rack.num_modules();
+        let osc = Arc::new(Additive::new(n, self.partials.len()));
+        controls[(n, 0)] = self.hz;
+        controls[(n, 1)] = self.amplitude;
+        for (k, p) in self.partials.iter().enumerate() {
+            controls[(n, k + 2)] = *p;
+        }
+        rack.push(osc.clone());
+        osc
     }
-    props!(interval, set_interval, 0);
 }
 
-impl Signal for Clock {
+impl Signal for Additive {
     tag!();
     fn signal(
         &self,
@@ -482,19 +907,1348 @@ impl Signal for Clock {
         _buffers: &mut Buffers,
         sample_rate: f32,
     ) {
-        let tag = self.tag;
-        let interval = self.interval(controls, outputs) * sample_rate;
-        let out;
-        if state[(tag, 0)] == 0.0 {
-            state[(tag, 0)] += 1.0;
-            out = 1.0;
+        let phase = state[(self.tag, 0)];
+        let hz = self.hz(controls, outputs);
+        let harmonics = if hz == 0.0 {
+            self.partials
         } else {
-            state[(tag, 0)] += 1.0;
-            while state[(tag, 0)] >= interval {
-                state[(tag, 0)] -= interval;
-            }
-            out = 0.0;
+            // Only partials strictly below Nyquist; none when hz is at or above it.
+            let below = (sample_rate / (2.0 * hz.abs())).ceil() as usize;
+            self.partials.min(below.saturating_sub(1))
+        };
+        let (s1, c1) = (phase * TAU).sin_cos();
+        let (mut prev, mut sin) = (0.0, s1);
+        let mut out = 0.0;
+        for k in 1..=harmonics {
+            out += self.partial(controls, outputs, k) * sin;
+            let next = 2.0 * c1 * sin - prev;
+            prev = sin;
+            sin = next;
+        }
+        state[(self.tag, 0)] = fract(phase + hz / sample_rate);
+        outputs[(self.tag, 0)] = out * self.amplitude(controls, outputs);
+    }
+}
+
+/// Frequencies and gains in dB of the first five formants of a tenor singing
+/// the vowels A, E, I, O and U.
+const VOWELS: [[(f32, f32); 5]; 5] = [
+    [
+        (650.0, 0.0),
+        (1080.0, -6.0),
+        (2650.0, -7.0),
+        (2900.0, -8.0),
+        (3250.0, -22.0),
+    ],
+    [
+        (400.0, 0.0),
+        (1700.0, -14.0),
+        (2600.0, -12.0),
+        (3200.0, -14.0),
+        (3580.0, -20.0),
+    ],
+    [
+        (290.0, 0.0),
+        (1870.0, -15.0),
+        (2800.0, -18.0),
+        (3250.0, -20.0),
+        (3540.0, -30.0),
+    ],
+    [
+        (400.0, 0.0),
+        (800.0, -10.0),
+        (2600.0, -12.0),
+        (2800.0, -12.0),
+        (3000.0, -26.0),
+    ],
+    [
+        (350.0, 0.0),
+        (600.0, -20.0),
+        (2700.0, -17.0),
+        (2900.0, -14.0),
+        (3300.0, -26.0),
+    ],
+];
+
+/// The formant frequencies and linear gains at `vowel`, from 0 for A to 4
+/// for U, interpolating between adjacent vowels.
+pub fn vowel_formants(vowel: f32) -> [(f32, f32); 5] {
+    let v = vowel.clamp(0.0, 4.0);
+    let i = (v.trunc() as usize).min(3);
+    let f = v - i as f32;
+    let mut formants = [(0.0, 0.0); 5];
+    for (k, formant) in formants.iter_mut().enumerate() {
+        let (hz0, db0) = VOWELS[i][k];
+        let (hz1, db1) = VOWELS[i + 1][k];
+        let db = db0 + f * (db1 - db0);
+        *formant = (hz0 + f * (hz1 - hz0), (10.0f32).powf(db / 20.0));
+    }
+    formants
+}
+
+/// A vocal formant oscillator using pulsar synthesis. Each fundamental
+/// period contains a sine at each formant frequency under a Hann window, so
+/// the formants stay put as the pitch changes. `vowel` morphs from A (0) to
+/// U (4) and `shift` moves all formants by that many semitones.
+#[derive(Copy, Clone)]
+pub struct Formant {
+    tag: Tag,
+}
+
+#[derive(Copy, Clone)]
+pub struct FormantBuilder {
+    hz: Control,
+    amplitude: Control,
+    vowel: Control,
+    shift: Control,
+}
+
+impl Formant {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(hz, set_hz, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(vowel, set_vowel, 2);
+    props!(shift, set_shift, 3);
+}
+
+impl FormantBuilder {
+    pub fn new() -> Self {
+        Self {
+            hz: 0.0.into(),
+            amplitude: 1.0.into(),
+            vowel: 0.0.into(),
+            shift: 0.0.into(),
+        }
+    }
+    build!(hz);
+    build!(amplitude);
+    build!(vowel);
+    build!(shift);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Formant> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.hz;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.vowel;
+        controls[(n, 3)] = self.shift;
+        let osc = Arc::new(Formant::new(n));
+        rack.push(osc.clone());
+        osc
+    }
+}
+
+impl Default for FormantBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signal for Formant {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let phase = state[(self.tag, 0)];
+        let hz = self.hz(controls, outputs);
+        let shift = (self.shift(controls, outputs) / 12.0).exp2();
+        let window = 0.5 - 0.5 * (TAU * phase).cos();
+        let mut out = 0.0;
+        if hz > 0.0 {
+            for (f, gain) in vowel_formants(self.vowel(controls, outputs)).iter() {
+                let formant = f * shift;
+                if formant < sample_rate / 2.0 {
+                    out += gain * (TAU * phase * formant / hz).sin();
+                }
+            }
+        }
+        state[(self.tag, 0)] = fract(phase + hz / sample_rate);
+        outputs[(self.tag, 0)] = 0.5 * window * out * self.amplitude(controls, outputs);
+    }
+}
+
+pub fn square_wave(n: u32) -> FourierOscBuilder {
+    let mut coefficients: Vec<f32> = Vec::new();
+    for i in 0..=n {
+        if i % 2 == 1 {
+            coefficients.push(1. / i as f32);
+        } else {
+            coefficients.push(0.);
+        }
+    }
+    FourierOscBuilder::new(coefficients)
+}
+
+pub fn triangle_wave(n: u32) -> FourierOscBuilder {
+    let mut coefficients: Vec<f32> = Vec::new();
+    for i in 0..=n {
+        if i % 2 == 1 {
+            let sgn = if i % 4 == 1 { -1.0 } else { 1.0 };
+            coefficients.push(sgn / (i * i) as f32);
+        } else {
+            coefficients.push(0.0);
+        }
+    }
+    FourierOscBuilder::new(coefficients)
+}
+
+/// Length of each frame of a `Wavetable` after resampling.
+const TABLE_SIZE: usize = 2048;
+/// Number of band-limited versions of each frame, one per octave.
+const TABLE_LEVELS: usize = 11;
+
+/// A wavetable oscillator. Each frame is a single cycle of a waveform and the
+/// `position` control morphs between adjacent frames. To avoid aliasing every
+/// frame is stored at each octave with the harmonics above the Nyquist
+/// frequency for that octave removed.
+#[derive(Clone)]
+pub struct Wavetable {
+    tag: Tag,
+    mipmaps: Vec<Vec<Vec<f32>>>,
+}
+
+#[derive(Clone)]
+pub struct WavetableBuilder {
+    frames: Vec<Vec<f32>>,
+    hz: Control,
+    amplitude: Control,
+    position: Control,
+}
+
+/// Resample a single cycle to `TABLE_SIZE` and return a copy for each level
+/// with only the lowest `TABLE_SIZE / 2^(level + 1)` harmonics.
+fn mipmap(frame: &[f32]) -> Vec<Vec<f32>> {
+    let n = frame.len();
+    let mut re: Vec<f32> = (0..TABLE_SIZE)
+        .map(|i| {
+            let x = i as f32 * n as f32 / TABLE_SIZE as f32;
+            let j = x.trunc() as usize;
+            let f = x - x.trunc();
+            (1.0 - f) * frame[j % n] + f * frame[(j + 1) % n]
+        })
+        .collect();
+    let mut im = vec![0.0; TABLE_SIZE];
+    fft(&mut re, &mut im, false);
+    (0..TABLE_LEVELS)
+        .map(|level| {
+            let harmonics = (TABLE_SIZE / 2) >> level;
+            let (mut lre, mut lim) = (re.clone(), im.clone());
+            for k in harmonics + 1..TABLE_SIZE - harmonics {
+                lre[k] = 0.0;
+                lim[k] = 0.0;
+            }
+            fft(&mut lre, &mut lim, true);
+            lre.iter().map(|x| x / TABLE_SIZE as f32).collect()
+        })
+        .collect()
+}
+
+fn lookup(table: &[f32], phase: f32) -> f32 {
+    let x = phase * TABLE_SIZE as f32;
+    let i = x.trunc() as usize;
+    let f = x - x.trunc();
+    (1.0 - f) * table[i % TABLE_SIZE] + f * table[(i + 1) % TABLE_SIZE]
+}
+
+impl Wavetable {
+    pub fn new<T: Into<Tag>>(tag: T, frames: &[Vec<f32>]) -> Self {
+        assert!(!frames.is_empty(), "A wavetable needs at least one frame");
+        let levels: Vec<Vec<Vec<f32>>> = frames.iter().map(|f| mipmap(f)).collect();
+        let mipmaps = (0..TABLE_LEVELS)
+            .map(|l| levels.iter().map(|f| f[l].clone()).collect())
+            .collect();
+        Self {
+            tag: tag.into(),
+            mipmaps,
+        }
+    }
+    pub fn num_frames(&self) -> usize {
+        self.mipmaps[0].len()
+    }
+    pub fn phase(&self, state: &State) -> f32 {
+        state[(self.tag, 0)]
+    }
+    pub fn set_phase(&self, state: &mut State, value: f32) {
+        state[(self.tag, 0)] = value;
+    }
+    props!(hz, set_hz, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(position, set_position, 2);
+}
+
+impl WavetableBuilder {
+    pub fn new(frames: Vec<Vec<f32>>) -> Self {
+        Self {
+            frames,
+            hz: 0.0.into(),
+            amplitude: 1.0.into(),
+            position: 0.0.into(),
+        }
+    }
+    /// A wavetable with a single frame.
+    pub fn single(table: Vec<f32>) -> Self {
+        Self::new(vec![table])
+    }
+    /// Load a wavetable from a wav file, splitting it into frames of
+    /// `frame_len` samples. Fails if the file is shorter than one frame.
+    #[cfg(feature = "wav")]
+    pub fn from_wav<P: AsRef<Path>>(path: P, frame_len: usize) -> Result<Self, hound::Error> {
+        if frame_len == 0 {
+            return Err(hound::Error::FormatError("frame length must not be 0"));
+        }
+        let (samples, _) = read_wav(path)?;
+        if samples.len() < frame_len {
+            return Err(hound::Error::FormatError(
+                "wav file is shorter than one wavetable frame",
+            ));
+        }
+        let frames = samples
+            .chunks_exact(frame_len)
+            .map(|f| f.to_vec())
+            .collect();
+        Ok(Self::new(frames))
+    }
+    build!(hz);
+    build!(amplitude);
+    build!(position);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Wavetable> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.hz;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.position;
+        let wt = Arc::new(Wavetable::new(n, &self.frames));
+        rack.push(wt.clone());
+        wt
+    }
+}
+
+impl Signal for Wavetable {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let phase = self.phase(state);
+        let hz = self.hz(controls, outputs);
+        let position = self.position(controls, outputs).clamp(0.0, 1.0);
+        let octaves = (TABLE_SIZE as f32 * hz.abs() / sample_rate).log2().ceil();
+        let level = (octaves.max(0.0) as usize).min(TABLE_LEVELS - 1);
+        let frames = &self.mipmaps[level];
+        let x = position * (frames.len() - 1) as f32;
+        let i = x.trunc() as usize;
+        let f = x - x.trunc();
+        let a = lookup(&frames[i], phase);
+        let b = lookup(&frames[(i + 1).min(frames.len() - 1)], phase);
+        self.set_phase(state, fract(phase + hz / sample_rate));
+        outputs[(self.tag, 0)] = self.amplitude(controls, outputs) * (a + f * (b - a));
+    }
+}
+
+/// Maximum number of voices in a `Unison` oscillator.
+pub const MAX_VOICES: usize = 16;
+
+/// A stack of detuned oscillators sharing one waveform. Voice `i` is detuned by
+/// `detune * x^curve` semitones and panned by `spread * x` where `x` runs
+/// evenly from -1 to 1 across the voices. Output 0 is the mono sum and outputs
+/// 1 and 2 are the left and right channels.
+#[derive(Clone)]
+pub struct Unison {
+    tag: Tag,
+    waveform: Waveform,
+    voices: usize,
+}
+
+pub struct UnisonBuilder {
+    waveform: Waveform,
+    voices: usize,
+    random_phase: f32,
+    hz: Control,
+    amplitude: Control,
+    arg: Control,
+    detune: Control,
+    curve: Control,
+    spread: Control,
+}
+
+impl UnisonBuilder {
+    pub fn new(waveform: Waveform, voices: usize) -> Self {
+        assert!(
+            voices > 0 && voices <= MAX_VOICES,
+            "A unison oscillator has between 1 and {} voices",
+            MAX_VOICES
+        );
+        Self {
+            waveform,
+            voices,
+            random_phase: 1.0,
+            hz: 0.0.into(),
+            amplitude: 1.0.into(),
+            arg: 0.5.into(),
+            detune: 0.2.into(),
+            curve: 1.0.into(),
+            spread: 1.0.into(),
+        }
+    }
+    /// How much to randomize the starting phase of each voice, from 0 for
+    /// all voices starting at phase 0 to 1 for fully random phases.
+    pub fn random_phase(&mut self, value: f32) -> &mut Self {
+        self.random_phase = value;
+        self
+    }
+    build!(hz);
+    build!(amplitude);
+    build!(arg);
+    build!(detune);
+    build!(curve);
+    build!(spread);
+
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls, state: &mut State) -> Arc<Unison> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.hz;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.arg;
+        controls[(n, 3)] = self.detune;
+        controls[(n, 4)] = self.curve;
+        controls[(n, 5)] = self.spread;
+        // The voice phases are drawn from a generator in the slot after them.
+        for i in 0..self.voices {
+            state[(n, i)] = self.random_phase * random(state, n.into(), MAX_VOICES);
+        }
+        let unison = Arc::new(Unison::new(n, self.waveform, self.voices));
+        rack.push(unison.clone());
+        unison
+    }
+}
+
+impl Unison {
+    pub fn new<T: Into<Tag>>(tag: T, waveform: Waveform, voices: usize) -> Self {
+        Self {
+            tag: tag.into(),
+            waveform,
+            voices,
+        }
+    }
+    pub fn voices(&self) -> usize {
+        self.voices
+    }
+    pub fn phase(&self, state: &State, voice: usize) -> f32 {
+        state[(self.tag, voice)]
+    }
+    pub fn set_phase(&self, state: &mut State, voice: usize, value: f32) {
+        state[(self.tag, voice)] = value;
+    }
+    /// Position of voice `i` from -1 to 1.
+    fn position(&self, i: usize) -> f32 {
+        if self.voices == 1 {
+            0.0
+        } else {
+            2.0 * i as f32 / (self.voices - 1) as f32 - 1.0
+        }
+    }
+    props!(hz, set_hz, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(arg, set_arg, 2);
+    props!(detune, set_detune, 3);
+    props!(curve, set_curve, 4);
+    props!(spread, set_spread, 5);
+}
+
+impl Signal for Unison {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let hz = self.hz(controls, outputs);
+        let arg = self.arg(controls, outputs);
+        let detune = self.detune(controls, outputs);
+        let curve = self.curve(controls, outputs);
+        let spread = self.spread(controls, outputs);
+        let (mut mono, mut left, mut right) = (0.0, 0.0, 0.0);
+        for i in 0..self.voices {
+            let x = self.position(i);
+            let semitones = if x == 0.0 {
+                0.0
+            } else {
+                detune * x.signum() * x.abs().powf(curve)
+            };
+            let dt = hz * (semitones / 12.0).exp2() / sample_rate;
+            let phase = self.phase(state, i);
+            let v = self.waveform.sample(phase, arg, dt);
+            let pan = (spread * x + 1.0) * consts::PI / 4.0;
+            mono += v;
+            left += v * pan.cos();
+            right += v * pan.sin();
+            self.set_phase(state, i, fract(phase + dt));
+        }
+        let amp = self.amplitude(controls, outputs) / (self.voices as f32).sqrt();
+        outputs[(self.tag, 0)] = amp * mono;
+        outputs[(self.tag, 1)] = amp * left;
+        outputs[(self.tag, 2)] = amp * right;
+    }
+}
+
+/// Casio CZ saw phase warp, the first half of the cosine is squeezed into
+/// `(1 - amount) / 2` of the cycle.
+pub fn pd_saw(phase: f32, amount: f32) -> f32 {
+    let d = (0.5 * (1.0 - amount)).clamp(0.01, 0.5);
+    if phase < d {
+        0.5 * phase / d
+    } else {
+        0.5 + 0.5 * (phase - d) / (1.0 - d)
+    }
+}
+
+/// Casio CZ square phase warp, each half cycle rises quickly and then holds.
+pub fn pd_square(phase: f32, amount: f32) -> f32 {
+    let d = (1.0 - amount).clamp(0.01, 1.0);
+    let half = if phase < 0.5 { 0.0 } else { 0.5 };
+    let q = 2.0 * (phase - half);
+    half + 0.5 * (q / d).min(1.0)
+}
+
+/// Casio CZ pulse phase warp, the whole cycle in `1 - amount` then a hold.
+pub fn pd_pulse(phase: f32, amount: f32) -> f32 {
+    let d = (1.0 - amount).clamp(0.01, 1.0);
+    (phase / d).min(1.0)
+}
+
+/// The window applied over each cycle in the resonant modes of
+/// `PhaseDistortion`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PdWindow {
+    None,
+    Saw,
+    Triangle,
+    Trapezoid,
+}
+
+impl PdWindow {
+    fn apply(&self, phase: f32) -> f32 {
+        match self {
+            PdWindow::None => 1.0,
+            PdWindow::Saw => 1.0 - phase,
+            PdWindow::Triangle => 1.0 - (2.0 * phase - 1.0).abs(),
+            PdWindow::Trapezoid => (2.0 - 2.0 * phase).min(1.0),
+        }
+    }
+}
+
+/// A phase distortion oscillator. The phase is warped by `warp` with the
+/// distortion `amount` before the cosine lookup. `resonance` multiplies the
+/// frequency of the cosine, which restarts every cycle and is shaped by the
+/// window, so the output is `1 - w * (1 - cos(2 pi resonance warped))`.
+#[derive(Copy, Clone)]
+pub struct PhaseDistortion {
+    tag: Tag,
+    warp: SignalFn,
+    window: PdWindow,
+}
+
+#[derive(Copy, Clone)]
+pub struct PhaseDistortionBuilder {
+    warp: SignalFn,
+    window: PdWindow,
+    hz: Control,
+    amplitude: Control,
+    amount: Control,
+    resonance: Control,
+}
+
+impl PhaseDistortion {
+    pub fn new<T: Into<Tag>>(tag: T, warp: SignalFn, window: PdWindow) -> Self {
+        Self {
+            tag: tag.into(),
+            warp,
+            window,
+        }
+    }
+    pub fn phase(&self, state: &State) -> f32 {
+        state[(self.tag, 0)]
+    }
+    pub fn set_phase(&self, state: &mut State, value: f32) {
+        state[(self.tag, 0)] = value;
+    }
+    props!(hz, set_hz, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(amount, set_amount, 2);
+    props!(resonance, set_resonance, 3);
+}
+
+impl PhaseDistortionBuilder {
+    pub fn new(warp: SignalFn) -> Self {
+        Self {
+            warp,
+            window: PdWindow::None,
+            hz: 0.0.into(),
+            amplitude: 1.0.into(),
+            amount: 0.0.into(),
+            resonance: 1.0.into(),
+        }
+    }
+    pub fn window(&mut self, value: PdWindow) -> &mut Self {
+        self.window = value;
+        self
+    }
+    build!(hz);
+    build!(amplitude);
+    build!(amount);
+    build!(resonance);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<PhaseDistortion> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.hz;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.amount;
+        controls[(n, 3)] = self.resonance;
+        let osc = Arc::new(PhaseDistortion::new(n, self.warp, self.window));
+        rack.push(osc.clone());
+        osc
+    }
+}
+
+impl Signal for PhaseDistortion {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let phase = self.phase(state);
+        let amount = self.amount(controls, outputs).clamp(0.0, 1.0);
+        let resonance = self.resonance(controls, outputs);
+        let warped = (self.warp)(phase, amount);
+        let w = self.window.apply(phase);
+        let out = 1.0 - w * (1.0 - (TAU * resonance * warped).cos());
+        self.set_phase(
+            state,
+            fract(phase + self.hz(controls, outputs) / sample_rate),
+        );
+        outputs[(self.tag, 0)] = self.amplitude(controls, outputs) * out;
+    }
+}
+
+/// A continuous chaotic system, the parameters `a` to `d` are listed for each.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Attractor {
+    /// sigma, rho and beta.
+    Lorenz,
+    /// a, b and c.
+    Rossler,
+    /// alpha, beta and the inner and outer slopes m0 and m1 of the diode.
+    Chua,
+}
+
+impl Attractor {
+    fn defaults(&self) -> [f32; 4] {
+        match self {
+            Attractor::Lorenz => [10.0, 28.0, 8.0 / 3.0, 0.0],
+            Attractor::Rossler => [0.2, 0.2, 5.7, 0.0],
+            Attractor::Chua => [15.6, 28.0, -1.143, -0.714],
+        }
+    }
+
+    /// The derivative at `v` with parameters `p`.
+    fn derivative(&self, v: [f32; 3], p: [f32; 4]) -> [f32; 3] {
+        let [x, y, z] = v;
+        match self {
+            Attractor::Lorenz => [p[0] * (y - x), x * (p[1] - z) - y, x * y - p[2] * z],
+            Attractor::Rossler => [-y - z, x + p[0] * y, p[1] + z * (x - p[2])],
+            Attractor::Chua => {
+                let f = p[3] * x + 0.5 * (p[2] - p[3]) * ((x + 1.0).abs() - (x - 1.0).abs());
+                [p[0] * (y - x - f), x - y + z, -p[1] * y]
+            }
+        }
+    }
+
+    /// Offset and scale to bring each coordinate to roughly [-1, 1].
+    fn normalize(&self, v: [f32; 3]) -> [f32; 3] {
+        let [x, y, z] = v;
+        match self {
+            Attractor::Lorenz => [x / 20.0, y / 27.0, (z - 25.0) / 25.0],
+            Attractor::Rossler => [x / 12.0, y / 12.0, z / 25.0],
+            Attractor::Chua => [x / 2.5, y / 0.5, z / 4.0],
+        }
+    }
+}
+
+/// A chaotic oscillator integrating an `Attractor` with fourth order
+/// Runge-Kutta. `rate` is the number of time units per second. Outputs 0, 1
+/// and 2 are x, y and z scaled to roughly [-1, 1]. If the integration blows
+/// up, for instance when `rate` is too high, it starts over.
+#[derive(Copy, Clone)]
+pub struct Chaotic {
+    tag: Tag,
+    attractor: Attractor,
+}
+
+#[derive(Copy, Clone)]
+pub struct ChaoticBuilder {
+    attractor: Attractor,
+    rate: Control,
+    amplitude: Control,
+    a: Control,
+    b: Control,
+    c: Control,
+    d: Control,
+}
+
+impl Chaotic {
+    pub fn new<T: Into<Tag>>(tag: T, attractor: Attractor) -> Self {
+        Self {
+            tag: tag.into(),
+            attractor,
+        }
+    }
+    props!(rate, set_rate, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(a, set_a, 2);
+    props!(b, set_b, 3);
+    props!(c, set_c, 4);
+    props!(d, set_d, 5);
+}
+
+impl ChaoticBuilder {
+    pub fn new(attractor: Attractor) -> Self {
+        let [a, b, c, d] = attractor.defaults();
+        Self {
+            attractor,
+            rate: 1.0.into(),
+            amplitude: 1.0.into(),
+            a: a.into(),
+            b: b.into(),
+            c: c.into(),
+            d: d.into(),
+        }
+    }
+    build!(rate);
+    build!(amplitude);
+    build!(a);
+    build!(b);
+    build!(c);
+    build!(d);
+    pub fn rack(
+        &self,
+        rack: &mut Rack,
+        controls: &mut Controls,
+        state: &mut State,
+    ) -> Arc<Chaotic> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.rate;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.a;
+        controls[(n, 3)] = self.b;
+        controls[(n, 4)] = self.c;
+        controls[(n, 5)] = self.d;
+        // Start away from the fixed point at the origin.
+        state[(n, 0)] = 0.1;
+        let chaotic = Arc::new(Chaotic::new(n, self.attractor));
+        rack.push(chaotic.clone());
+        chaotic
+    }
+}
+
+impl Signal for Chaotic {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let p = [
+            self.a(controls, outputs),
+            self.b(controls, outputs),
+            self.c(controls, outputs),
+            self.d(controls, outputs),
+        ];
+        let h = self.rate(controls, outputs) / sample_rate;
+        let v = [state[(tag, 0)], state[(tag, 1)], state[(tag, 2)]];
+        let step = |k: [f32; 3], s: f32| [v[0] + s * k[0], v[1] + s * k[1], v[2] + s * k[2]];
+        let k1 = self.attractor.derivative(v, p);
+        let k2 = self.attractor.derivative(step(k1, h / 2.0), p);
+        let k3 = self.attractor.derivative(step(k2, h / 2.0), p);
+        let k4 = self.attractor.derivative(step(k3, h), p);
+        let amp = self.amplitude(controls, outputs);
+        for i in 0..3 {
+            state[(tag, i)] = v[i] + h / 6.0 * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]);
+        }
+        if (0..3).any(|i| !state[(tag, i)].is_finite()) {
+            state[(tag, 0)] = 0.1;
+            state[(tag, 1)] = 0.0;
+            state[(tag, 2)] = 0.0;
+        }
+        let out = self.attractor.normalize(v);
+        for (i, x) in out.iter().enumerate() {
+            outputs[(tag, i)] = amp * x;
+        }
+    }
+}
+
+/// The logistic map `x <- r x (1 - x)` iterated `rate` times per second and
+/// held in between, output scaled to [-1, 1]. `r` is clamped to [0, 4], the
+/// range that keeps `x` in [0, 1]. A value that is not finite restarts the
+/// map from `x0`.
+#[derive(Copy, Clone)]
+pub struct Logistic {
+    tag: Tag,
+}
+
+#[derive(Copy, Clone)]
+pub struct LogisticBuilder {
+    rate: Control,
+    amplitude: Control,
+    r: Control,
+    x0: f32,
+}
+
+impl Logistic {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(rate, set_rate, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(r, set_r, 2);
+}
+
+impl LogisticBuilder {
+    pub fn new() -> Self {
+        Self {
+            rate: 1.0.into(),
+            amplitude: 1.0.into(),
+            r: 3.9.into(),
+            x0: 0.5,
+        }
+    }
+    build!(rate);
+    build!(amplitude);
+    build!(r);
+    /// The starting value between 0 and 1.
+    pub fn x0(&mut self, value: f32) -> &mut Self {
+        self.x0 = value;
+        self
+    }
+    pub fn rack(
+        &self,
+        rack: &mut Rack,
+        controls: &mut Controls,
+        state: &mut State,
+    ) -> Arc<Logistic> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.rate;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.r;
+        state[(n, 1)] = self.x0;
+        state[(n, 2)] = self.x0;
+        let logistic = Arc::new(Logistic::new(n));
+        rack.push(logistic.clone());
+        logistic
+    }
+}
+
+impl Default for LogisticBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signal for Logistic {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let x = state[(tag, 1)];
+        outputs[(tag, 0)] = self.amplitude(controls, outputs) * (2.0 * x - 1.0);
+        state[(tag, 0)] += self.rate(controls, outputs) / sample_rate;
+        if state[(tag, 0)] >= 1.0 {
+            state[(tag, 0)] = fract(state[(tag, 0)]);
+            let r = self.r(controls, outputs).clamp(0.0, 4.0);
+            let next = r * x * (1.0 - x);
+            state[(tag, 1)] = if next.is_finite() {
+                next
+            } else {
+                state[(tag, 2)]
+            };
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    SawUp,
+    SawDown,
+    Square,
+    /// A new random value every cycle.
+    SampleHold,
+}
+
+impl From<usize> for LfoShape {
+    fn from(n: usize) -> Self {
+        match n {
+            0 => LfoShape::Sine,
+            1 => LfoShape::Triangle,
+            2 => LfoShape::SawUp,
+            3 => LfoShape::SawDown,
+            4 => LfoShape::Square,
+            _ => LfoShape::SampleHold,
+        }
+    }
+}
+
+impl From<LfoShape> for Control {
+    fn from(shape: LfoShape) -> Self {
+        Control::I(shape as usize)
+    }
+}
+
+/// Where an `Lfo` gets its rate from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LfoSync {
+    /// `hz` cycles per second.
+    Free,
+    /// One cycle every `division` beats at `bpm`.
+    Bpm,
+    /// One cycle every `division` periods of the pulses at the `clock` input.
+    Clock,
+}
+
+/// A low frequency oscillator. `phase` offsets the cycle, `fade` is a fade in
+/// time in seconds and a rising edge on `gate` restarts the cycle and the
+/// fade. A unipolar lfo goes from 0 to `amplitude` instead of `-amplitude`
+/// to `amplitude`.
+#[derive(Copy, Clone)]
+pub struct Lfo {
+    tag: Tag,
+    sync: LfoSync,
+}
+
+#[derive(Copy, Clone)]
+pub struct LfoBuilder {
+    sync: LfoSync,
+    hz: Control,
+    amplitude: Control,
+    shape: Control,
+    phase: Control,
+    unipolar: Control,
+    fade: Control,
+    gate: Control,
+    bpm: Control,
+    division: Control,
+    clock: Control,
+}
+
+impl Lfo {
+    pub fn new<T: Into<Tag>>(tag: T, sync: LfoSync) -> Self {
+        Self {
+            tag: tag.into(),
+            sync,
+        }
+    }
+    props!(hz, set_hz, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(phase, set_phase, 3);
+    props!(fade, set_fade, 5);
+    props!(gate, set_gate, 6);
+    props!(bpm, set_bpm, 7);
+    props!(division, set_division, 8);
+    props!(clock, set_clock, 9);
+
+    pub fn shape(&self, controls: &Controls, outputs: &Outputs) -> LfoShape {
+        let inp = controls[(self.tag, 2)];
+        outputs
+            .integer(inp)
+            .expect("shape must be Control::I")
+            .into()
+    }
+
+    pub fn set_shape(&self, controls: &mut Controls, value: LfoShape) {
+        controls[(self.tag, 2)] = value.into();
+    }
+
+    pub fn unipolar(&self, controls: &Controls, outputs: &Outputs) -> bool {
+        let inp = controls[(self.tag, 4)];
+        outputs.boolean(inp).expect("unipolar must be Control::B")
+    }
+
+    pub fn set_unipolar(&self, controls: &mut Controls, value: bool) {
+        controls[(self.tag, 4)] = value.into();
+    }
+
+    /// The current rate in hz.
+    pub fn rate(
+        &self,
+        controls: &Controls,
+        state: &State,
+        outputs: &Outputs,
+        sample_rate: f32,
+    ) -> f32 {
+        let division = self.division(controls, outputs);
+        match self.sync {
+            LfoSync::Free => self.hz(controls, outputs),
+            LfoSync::Bpm => self.bpm(controls, outputs) / (60.0 * division),
+            LfoSync::Clock => {
+                let period = state[(self.tag, 7)];
+                if period > 0.0 {
+                    sample_rate / (period * division)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+impl LfoBuilder {
+    pub fn new() -> Self {
+        Self {
+            sync: LfoSync::Free,
+            hz: 1.0.into(),
+            amplitude: 1.0.into(),
+            shape: LfoShape::Sine.into(),
+            phase: 0.0.into(),
+            unipolar: false.into(),
+            fade: 0.0.into(),
+            gate: 0.0.into(),
+            bpm: 120.0.into(),
+            division: 1.0.into(),
+            clock: 0.0.into(),
+        }
+    }
+    pub fn sync(&mut self, value: LfoSync) -> &mut Self {
+        self.sync = value;
+        self
+    }
+    build!(hz);
+    build!(amplitude);
+    build!(shape);
+    build!(phase);
+    build!(unipolar);
+    build!(fade);
+    build!(gate);
+    build!(bpm);
+    build!(division);
+    build!(clock);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Lfo> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.hz;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.shape;
+        controls[(n, 3)] = self.phase;
+        controls[(n, 4)] = self.unipolar;
+        controls[(n, 5)] = self.fade;
+        controls[(n, 6)] = self.gate;
+        controls[(n, 7)] = self.bpm;
+        controls[(n, 8)] = self.division;
+        controls[(n, 9)] = self.clock;
+        let lfo = Arc::new(Lfo::new(n, self.sync));
+        rack.push(lfo.clone());
+        lfo
+    }
+}
+
+impl Default for LfoBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signal for Lfo {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        // Slots: 0 phase, 1 time since trigger, 2 gate, 3 held value,
+        // 4 random generator, 5 clock, 6 samples since clock, 7 clock period,
+        // 8 is set once the first clock pulse has arrived and 9 once the
+        // value for this cycle has been drawn.
+        let gate = self.gate(controls, outputs);
+        if state[(tag, 2)] <= 0.0 && gate > 0.0 {
+            state[(tag, 0)] = 0.0;
+            state[(tag, 1)] = 0.0;
+            state[(tag, 9)] = 0.0;
+        }
+        state[(tag, 2)] = gate;
+        let clock = self.clock(controls, outputs);
+        state[(tag, 6)] += 1.0;
+        if state[(tag, 5)] <= 0.0 && clock > 0.0 {
+            if state[(tag, 8)] != 0.0 {
+                state[(tag, 7)] = state[(tag, 6)];
+            }
+            state[(tag, 6)] = 0.0;
+            state[(tag, 8)] = 1.0;
+        }
+        state[(tag, 5)] = clock;
+        let shape = self.shape(controls, outputs);
+        let phase = state[(tag, 0)];
+        if state[(tag, 9)] == 0.0 {
+            state[(tag, 3)] = 2.0 * random(state, tag, 4) - 1.0;
+            state[(tag, 9)] = 1.0;
+        }
+        let p = fract(phase + self.phase(controls, outputs));
+        let mut value = match shape {
+            LfoShape::Sine => (TAU * p).sin(),
+            LfoShape::Triangle => triangle_osc(p, 0.0),
+            LfoShape::SawUp => 2.0 * p - 1.0,
+            LfoShape::SawDown => 1.0 - 2.0 * p,
+            LfoShape::Square => square_osc(p, 0.5),
+            LfoShape::SampleHold => state[(tag, 3)],
+        };
+        if self.unipolar(controls, outputs) {
+            value = 0.5 * (value + 1.0);
+        }
+        let fade = self.fade(controls, outputs);
+        let gain = if fade > 0.0 {
+            (state[(tag, 1)] / fade).min(1.0)
+        } else {
+            1.0
+        };
+        state[(tag, 1)] += 1.0 / sample_rate;
+        let ph = phase + self.rate(controls, state, outputs, sample_rate) / sample_rate;
+        if ph >= 1.0 {
+            state[(tag, 9)] = 0.0;
+        }
+        state[(tag, 0)] = ph - ph.floor();
+        outputs[(tag, 0)] = gain * value * self.amplitude(controls, outputs);
+    }
+}
+
+/// A `SynthModule` that emits 1.0 every `interval` seconds otherwise it emits
+/// 0.0.
+#[derive(Copy, Clone)]
+pub struct Clock {
+    tag: Tag,
+}
+
+#[derive(Copy, Clone)]
+pub struct ClockBuilder {
+    interval: Control,
+}
+
+impl ClockBuilder {
+    pub fn new<T: Into<Control>>(interval: T) -> Self {
+        Self {
+            interval: interval.into(),
+        }
+    }
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<Clock> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.interval;
+        let clock = Arc::new(Clock::new(n));
+        rack.push(clock.clone());
+        clock
+    }
+}
+
+impl Clock {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(interval, set_interval, 0);
+}
+
+impl Signal for Clock {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let interval = self.interval(controls, outputs) * sample_rate;
+        let out;
+        if state[(tag, 0)] == 0.0 {
+            state[(tag, 0)] += 1.0;
+            out = 1.0;
+        } else {
+            state[(tag, 0)] += 1.0;
+            while state[(tag, 0)] >= interval {
+                state[(tag, 0)] -= interval;
+            }
+            out = 0.0;
+        }
+        outputs[(self.tag, 0)] = out;
+    }
+}
+
+/// A musical clock running at `bpm` beats per minute. Outputs 0 to 3 are
+/// pulses on every beat, bar, division and PPQN tick, output 4 is the song
+/// position in beats and 5 the tempo. There are `division` division pulses
+/// per beat, every other one is delayed by `swing` (0 is straight, 1 moves it
+/// halfway to the next pulse). The clock advances while the `run` gate is
+/// above 0.5 and a rising edge on `reset` jumps back to the start.
+#[derive(Copy, Clone)]
+pub struct TempoClock {
+    tag: Tag,
+}
+
+#[derive(Copy, Clone)]
+pub struct TempoClockBuilder {
+    bpm: Control,
+    beats_per_bar: Control,
+    division: Control,
+    ppqn: Control,
+    swing: Control,
+    run: Control,
+    reset: Control,
+}
+
+impl TempoClock {
+    pub fn new<T: Into<Tag>>(tag: T) -> Self {
+        Self { tag: tag.into() }
+    }
+    props!(bpm, set_bpm, 0);
+    props!(beats_per_bar, set_beats_per_bar, 1);
+    props!(division, set_division, 2);
+    props!(ppqn, set_ppqn, 3);
+    props!(swing, set_swing, 4);
+    props!(run, set_run, 5);
+    props!(reset, set_reset, 6);
+
+    /// The song position in beats.
+    pub fn position(&self, state: &State) -> f32 {
+        state[(self.tag, 0)] + state[(self.tag, 4)]
+    }
+
+    pub fn set_position(&self, state: &mut State, value: f32) {
+        state[(self.tag, 0)] = value.floor();
+        state[(self.tag, 4)] = value - value.floor();
+        state[(self.tag, 3)] = 0.0;
+    }
+}
+
+impl TempoClockBuilder {
+    pub fn new<T: Into<Control>>(bpm: T) -> Self {
+        Self {
+            bpm: bpm.into(),
+            beats_per_bar: 4.0.into(),
+            division: 4.0.into(),
+            ppqn: 24.0.into(),
+            swing: 0.0.into(),
+            run: 1.0.into(),
+            reset: 0.0.into(),
+        }
+    }
+    build!(beats_per_bar);
+    build!(division);
+    build!(ppqn);
+    build!(swing);
+    build!(run);
+    build!(reset);
+    pub fn rack(&self, rack: &mut Rack, controls: &mut Controls) -> Arc<TempoClock> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.bpm;
+        controls[(n, 1)] = self.beats_per_bar;
+        controls[(n, 2)] = self.division;
+        controls[(n, 3)] = self.ppqn;
+        controls[(n, 4)] = self.swing;
+        controls[(n, 5)] = self.run;
+        controls[(n, 6)] = self.reset;
+        let clock = Arc::new(TempoClock::new(n));
+        rack.push(clock.clone());
+        clock
+    }
+}
+
+/// The song position in beats from its whole and fractional parts, in double
+/// precision so that pulses can still be counted late in a song.
+fn beats(whole: f32, frac: f32) -> f64 {
+    whole as f64 + frac as f64
+}
+
+/// The number of swung pulses at or before `x` beats, two per `pair` beats.
+fn swung_pulses(x: f64, pair: f32, swing: f32) -> f64 {
+    let u = x / pair as f64;
+    let offbeat = if u - u.floor() >= 0.5 + 0.25 * swing as f64 {
+        1.0
+    } else {
+        0.0
+    };
+    2.0 * u.floor() + offbeat
+}
+
+impl Signal for TempoClock {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let bpm = self.bpm(controls, outputs);
+        let run = self.run(controls, outputs) > 0.5;
+        // Slots: 0 and 4 the whole and fractional beats of the position, 2 and
+        // 5 those of the previous position, 1 reset and 3 is set after the
+        // pulses at the starting position have been sent. Keeping the parts
+        // apart stops the position from losing precision as it grows.
+        let reset = self.reset(controls, outputs);
+        if state[(tag, 1)] <= 0.0 && reset > 0.0 {
+            self.set_position(state, 0.0);
+        }
+        state[(tag, 1)] = reset;
+        let (whole, frac) = (state[(tag, 0)], state[(tag, 4)]);
+        let pos = beats(whole, frac);
+        let prev = beats(state[(tag, 2)], state[(tag, 5)]);
+        let started = state[(tag, 3)] != 0.0;
+        let crossed = |per_beat: f32| {
+            let per_beat = per_beat as f64;
+            let pulse = run && (!started || (pos * per_beat).floor() > (prev * per_beat).floor());
+            pulse as i32 as f32
+        };
+        let pair = 2.0 / self.division(controls, outputs);
+        let swing = self.swing(controls, outputs);
+        let division =
+            run && (!started || swung_pulses(pos, pair, swing) > swung_pulses(prev, pair, swing));
+        outputs[(tag, 0)] = crossed(1.0);
+        outputs[(tag, 1)] = crossed(1.0 / self.beats_per_bar(controls, outputs));
+        outputs[(tag, 2)] = division as i32 as f32;
+        outputs[(tag, 3)] = crossed(self.ppqn(controls, outputs));
+        outputs[(tag, 4)] = whole + frac;
+        outputs[(tag, 5)] = bpm;
+        if run {
+            state[(tag, 2)] = whole;
+            state[(tag, 5)] = frac;
+            state[(tag, 3)] = 1.0;
+            let next = frac + bpm / (60.0 * sample_rate);
+            state[(tag, 0)] = whole + next.floor();
+            state[(tag, 4)] = next - next.floor();
         }
-        outputs[(self.tag, 0)] = out;
     }
 }
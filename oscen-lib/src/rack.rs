@@ -1,6 +1,8 @@
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
 use arr_macro::arr;
-use std::ops::{Index, IndexMut};
-use std::sync::Arc;
+use core::ops::{Index, IndexMut};
+#[cfg(not(feature = "std"))]
+use num::Float;
 
 pub type SignalFn = fn(f32, f32) -> f32;
 
@@ -35,9 +37,55 @@ impl From<usize> for Tag {
     }
 }
 
+/// The response of a scaled connection to its source.
+#[derive(Debug, Copy, Clone)]
+pub enum Curve {
+    /// `offset + gain * x`.
+    Linear,
+    /// `offset * 2^(gain * x)`, so `gain` is in octaves per unit of the source,
+    /// useful for modulating frequencies.
+    Exponential,
+}
+
+/// An attenuverter and offset applied to the source of a `Control::S`
+/// connection, like the depth and bias knobs of a hardware modular.
+#[derive(Debug, Copy, Clone)]
+pub struct Scale {
+    pub gain: f32,
+    pub offset: f32,
+    pub curve: Curve,
+}
+
+impl Scale {
+    pub fn new(gain: f32, offset: f32) -> Self {
+        Self {
+            gain,
+            offset,
+            curve: Curve::Linear,
+        }
+    }
+    pub fn exp(gain: f32, offset: f32) -> Self {
+        Self {
+            gain,
+            offset,
+            curve: Curve::Exponential,
+        }
+    }
+    pub fn apply(&self, x: f32) -> f32 {
+        match self.curve {
+            Curve::Linear => self.offset + self.gain * x,
+            Curve::Exponential => self.offset * (self.gain * x).exp2(),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Control {
     V(Tag, usize),
+    /// Like `V` but the source is scaled and offset before it is used.
+    /// The output index is a `u8` so that `Control` stays the size of `V` and
+    /// the `Controls` table still fits on the stack.
+    S(Tag, u8, Scale),
     F(f32),
     B(bool),
     I(usize),
@@ -76,6 +124,12 @@ impl From<Tag> for Control {
     }
 }
 
+impl From<(Tag, Scale)> for Control {
+    fn from((t, s): (Tag, Scale)) -> Self {
+        Control::S(t, 0, s)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Controls([[Control; MAX_CONTROLS]; MAX_MODULES]);
 
@@ -130,6 +184,7 @@ impl Outputs {
         match ctrl {
             Control::F(p) => Some(p),
             Control::V(n, i) => Some(self.0[n.get()][i]),
+            Control::S(n, i, s) => Some(s.apply(self.0[n.get()][i as usize])),
             _ => None,
         }
     }
@@ -271,6 +326,32 @@ where
     }
 }
 
+impl<T> Index<usize> for RingBuffer<T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.buffer[index]
+    }
+}
+
+impl<T> IndexMut<usize> for RingBuffer<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.buffer[index]
+    }
+}
+
+/// Linear interpolation from `a` to `b`.
+pub(crate) fn lerp(a: f32, b: f32, f: f32) -> f32 {
+    (1.0 - f) * a + f * b
+}
+
+/// Hermite cubic interpolation between `v1` and `v2`.
+pub(crate) fn hermite(v0: f32, v1: f32, v2: f32, v3: f32, f: f32) -> f32 {
+    let a1 = 0.5 * (v2 - v0);
+    let a2 = v0 - 2.5 * v1 + 2.0 * v2 - 0.5 * v3;
+    let a3 = 0.5 * (v3 - v0) + 1.5 * (v1 - v2);
+    a3 * f * f * f + a2 * f * f + a1 * f + v1
+}
+
 impl RingBuffer {
     pub fn new32(sample_rate: f32) -> Self {
         let buffer = vec![0.0; sample_rate as usize];
@@ -280,7 +361,7 @@ impl RingBuffer {
     pub fn get_linear(&self, delay: f32) -> f32 {
         let rp = self.read_pos(delay);
         let f = rp - rp.trunc();
-        (1.0 - f) * self.get(delay) + f * self.get_offset(delay, 1)
+        lerp(self.get(delay), self.get_offset(delay, 1), f)
     }
 
     /// Hermite cubic polynomial interpolation.
@@ -290,10 +371,7 @@ impl RingBuffer {
         let v2 = self.get_offset(delay, 1);
         let v3 = self.get_offset(delay, 2);
         let f = self.read_pos(delay) - self.read_pos(delay).trunc();
-        let a1 = 0.5 * (v2 - v0);
-        let a2 = v0 - 2.5 * v1 + 2.0 * v2 - 0.5 * v3;
-        let a3 = 0.5 * (v3 - v0) + 1.5 * (v1 - v2);
-        a3 * f * f * f + a2 * f * f + a1 * f + v1
+        hermite(v0, v1, v2, v3, f)
     }
 }
 
@@ -333,6 +411,22 @@ pub trait Signal {
     /// modules.
     fn tag(&self) -> Tag;
     fn modify_tag(&mut self, f: fn(Tag) -> Tag);
+    /// The modules whose signal passes through this one, e.g. the `wave` of a
+    /// filter. Modulation sources are not included.
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![]
+    }
+    /// The delay in samples that this module adds to the signal from its
+    /// inputs.
+    fn latency(
+        &self,
+        _controls: &Controls,
+        _outputs: &Outputs,
+        _buffers: &Buffers,
+        _sample_rate: f32,
+    ) -> f32 {
+        0.0
+    }
     /// Responsible for updating the any inputs including `phase` and returning the next signal
     /// output.
     fn signal(
@@ -359,6 +453,24 @@ macro_rules! tag {
     };
 }
 
+/// Takes the place of a module that has been detached from a `Rack`.
+struct Detached {
+    tag: Tag,
+}
+
+impl Signal for Detached {
+    tag!();
+    fn signal(
+        &self,
+        _controls: &Controls,
+        _state: &mut State,
+        _outputs: &mut Outputs,
+        _buffers: &mut Buffers,
+        _sample_rate: f32,
+    ) {
+    }
+}
+
 /// A Rack is a topologically sorted `Array` of Synth Modules. A synth is one or
 /// more racks.
 pub struct Rack(Vec<Arc<dyn Signal + Send + Sync>>);
@@ -373,6 +485,28 @@ impl Rack {
     pub fn push(&mut self, module: Arc<dyn Signal + Send + Sync>) {
         self.0.push(module);
     }
+    /// Remove the modules from `start` on and return them so that they can be
+    /// run by another module. Each slot is left with a module that does
+    /// nothing so that modules racked later still get unique tags.
+    pub fn detach(&mut self, start: usize) -> Vec<Arc<dyn Signal + Send + Sync>> {
+        let mut detached = vec![];
+        for slot in self.0[start..].iter_mut() {
+            let placeholder = Arc::new(Detached { tag: slot.tag() });
+            detached.push(core::mem::replace(slot, placeholder));
+        }
+        detached
+    }
+    /// The latency in samples of the signal path ending at each module, indexed
+    /// by tag.
+    pub fn latencies(
+        &self,
+        controls: &Controls,
+        outputs: &Outputs,
+        buffers: &Buffers,
+        sample_rate: f32,
+    ) -> Vec<f32> {
+        path_latencies(&self.0, controls, outputs, buffers, sample_rate)
+    }
     /// Call the `signal` function for each module in turn returning the vector
     /// of outpts in the last module.
     pub fn play(
@@ -402,6 +536,27 @@ impl Rack {
     }
 }
 
+/// The latency of each module in a topologically sorted list of modules is its
+/// own latency plus the largest latency of its inputs.
+pub fn path_latencies(
+    modules: &[Arc<dyn Signal + Send + Sync>],
+    controls: &Controls,
+    outputs: &Outputs,
+    buffers: &Buffers,
+    sample_rate: f32,
+) -> Vec<f32> {
+    let mut latencies = vec![0.0; MAX_MODULES];
+    for module in modules {
+        let input = module
+            .inputs(controls)
+            .iter()
+            .fold(0.0, |acc: f32, t| acc.max(latencies[t.get()]));
+        latencies[module.tag().get()] =
+            input + module.latency(controls, outputs, buffers, sample_rate);
+    }
+    latencies
+}
+
 /// Generate the Environment variables needed for the synth.
 pub fn tables() -> (Rack, Box<Controls>, Box<State>, Box<Outputs>, Box<Buffers>) {
     (
@@ -455,4 +610,25 @@ mod tests {
         let result = rb.get_cubic(delay);
         assert_eq!(result, 3.75, "get_cubic returned {}, expected 3.75", result);
     }
+    #[test]
+    fn scaled_control() {
+        let mut outputs = Outputs::new();
+        outputs[(3, 0)] = 0.5;
+        let result = outputs.value(Control::S(3.into(), 0, Scale::new(-2.0, 1.0)));
+        assert_eq!(
+            result,
+            Some(0.0),
+            "linear returned {:?}, expected 0.0",
+            result
+        );
+        let result = outputs.value((Tag(3), Scale::exp(2.0, 100.0)).into());
+        assert_eq!(
+            result,
+            Some(200.0),
+            "exponential returned {:?}, expected 200.0",
+            result
+        );
+        // A scaled connection must not make the controls table any larger.
+        assert!(core::mem::size_of::<Control>() <= 3 * core::mem::size_of::<usize>());
+    }
 }
@@ -0,0 +1,465 @@
+use crate::rack::*;
+use crate::utils::random;
+#[cfg(feature = "wav")]
+use crate::utils::read_wav;
+use crate::{build, props, tag};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num::Float;
+#[cfg(feature = "wav")]
+use std::path::Path;
+
+/// How a `Sampler` moves through the region between `start` and `end`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlayMode {
+    /// Play the region once and stop.
+    OneShot,
+    /// Jump back to the start at the end of the region.
+    Forward,
+    /// Reverse direction at each end of the region.
+    PingPong,
+}
+
+/// How a `Sampler` reads between samples.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    None,
+    Linear,
+    Cubic,
+}
+
+/// Plays a sample stored in the module's `RingBuffer`. Playback starts from
+/// `start` when `gate` or `trigger_in` crosses zero going up. `start` and `end`
+/// are fractions of the sample length and `rate` is the playback speed, 1
+/// being the original pitch. A gated sampler stops when the gate goes back to
+/// zero. Interpolation only reads samples inside the region, wrapping to
+/// `start` when looping forward and clamping to its ends otherwise.
+#[derive(Clone)]
+pub struct Sampler {
+    tag: Tag,
+    sample_rate: f32,
+    mode: PlayMode,
+    interpolation: Interpolation,
+    gated: bool,
+}
+
+impl Sampler {
+    pub fn new<T: Into<Tag>>(
+        tag: T,
+        sample_rate: f32,
+        mode: PlayMode,
+        interpolation: Interpolation,
+        gated: bool,
+    ) -> Self {
+        Self {
+            tag: tag.into(),
+            sample_rate,
+            mode,
+            interpolation,
+            gated,
+        }
+    }
+    props!(rate, set_rate, 0);
+    props!(amplitude, set_amplitude, 1);
+    props!(start, set_start, 2);
+    props!(end, set_end, 3);
+    props!(gate, set_gate, 4);
+    props!(trigger_in, set_trigger_in, 5);
+
+    /// The current playback position in samples.
+    pub fn position(&self, state: &State) -> f32 {
+        state[(self.tag, 0)]
+    }
+    pub fn playing(&self, state: &State) -> bool {
+        state[(self.tag, 2)] != 0.0
+    }
+    /// Restart playback from `start` without a gate.
+    pub fn trigger(&self, state: &mut State) {
+        state[(self.tag, 1)] = 1.0;
+        state[(self.tag, 2)] = 1.0;
+        state[(self.tag, 4)] = 1.0;
+    }
+
+    /// Read sample `i`, moved into the region between `first` and `last`.
+    fn at(&self, buffer: &RingBuffer, i: f32, first: f32, last: f32) -> f32 {
+        let i = if self.mode == PlayMode::Forward {
+            let n = last - first + 1.0;
+            let k = i - first;
+            first + k - n * (k / n).floor()
+        } else {
+            i.clamp(first, last)
+        };
+        // The buffer's write position is 0 so reading `-i` reads index i.
+        buffer.get(-i)
+    }
+}
+
+impl Signal for Sampler {
+    tag!();
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        let buffer = buffers.buffers(tag);
+        let len = buffer.len() as f32;
+        let start = (self.start(controls, outputs).clamp(0.0, 1.0) * len).min(len - 1.0);
+        let end = (self.end(controls, outputs).clamp(0.0, 1.0) * len)
+            .max(start + 1.0)
+            .min(len);
+        let gate = self.gate(controls, outputs);
+        let trigger = self.trigger_in(controls, outputs);
+        if (state[(tag, 3)] <= 0.0 && gate > 0.0) || (state[(tag, 5)] <= 0.0 && trigger > 0.0) {
+            self.trigger(state);
+        }
+        state[(tag, 3)] = gate;
+        state[(tag, 5)] = trigger;
+        if self.gated && gate <= 0.0 {
+            state[(tag, 2)] = 0.0;
+        }
+        // Slot 4 flags a trigger, the position is set here where the region
+        // is known.
+        if state[(tag, 4)] != 0.0 {
+            state[(tag, 0)] = start;
+            state[(tag, 4)] = 0.0;
+        }
+        if !self.playing(state) {
+            outputs[(tag, 0)] = 0.0;
+            return;
+        }
+        let pos = self.position(state);
+        let (first, last) = (start.floor(), end.ceil() - 1.0);
+        let at = |i: f32| self.at(buffer, i, first, last);
+        let (i, f) = (pos.floor(), pos - pos.floor());
+        let value = match self.interpolation {
+            Interpolation::None => at(i),
+            Interpolation::Linear => lerp(at(i), at(i + 1.0), f),
+            Interpolation::Cubic => hermite(at(i - 1.0), at(i), at(i + 1.0), at(i + 2.0), f),
+        };
+        outputs[(tag, 0)] = self.amplitude(controls, outputs) * value;
+        let last = end - 1.0;
+        let mut dir = state[(tag, 1)];
+        let mut pos = pos + dir * self.rate(controls, outputs) * self.sample_rate / sample_rate;
+        match self.mode {
+            PlayMode::OneShot => {
+                if pos >= end || pos < start {
+                    state[(tag, 2)] = 0.0;
+                }
+            }
+            PlayMode::Forward => {
+                let n = end - start;
+                while pos >= end {
+                    pos -= n;
+                }
+                while pos < start {
+                    pos += n;
+                }
+            }
+            PlayMode::PingPong => {
+                if pos > last {
+                    pos = (2.0 * last - pos).max(start);
+                    dir = -dir;
+                } else if pos < start {
+                    pos = (2.0 * start - pos).min(last);
+                    dir = -dir;
+                }
+            }
+        }
+        state[(tag, 0)] = pos;
+        state[(tag, 1)] = dir;
+    }
+}
+
+#[derive(Clone)]
+pub struct SamplerBuilder {
+    samples: Vec<f32>,
+    sample_rate: f32,
+    mode: PlayMode,
+    interpolation: Interpolation,
+    gated: bool,
+    rate: Control,
+    amplitude: Control,
+    start: Control,
+    end: Control,
+    gate: Control,
+    trigger_in: Control,
+}
+
+impl SamplerBuilder {
+    /// A sampler for `samples` recorded at `sample_rate`.
+    pub fn new(samples: Vec<f32>, sample_rate: f32) -> Self {
+        assert!(!samples.is_empty(), "A sampler needs at least one sample");
+        Self {
+            samples,
+            sample_rate,
+            mode: PlayMode::OneShot,
+            interpolation: Interpolation::Linear,
+            gated: false,
+            rate: 1.0.into(),
+            amplitude: 1.0.into(),
+            start: 0.0.into(),
+            end: 1.0.into(),
+            gate: 0.0.into(),
+            trigger_in: 0.0.into(),
+        }
+    }
+    /// Load the sample from a wav file, mixed down to mono.
+    #[cfg(feature = "wav")]
+    pub fn from_wav<P: AsRef<Path>>(path: P) -> Result<Self, hound::Error> {
+        let (samples, sample_rate) = read_wav(path)?;
+        Ok(Self::new(samples, sample_rate))
+    }
+    pub fn mode(&mut self, value: PlayMode) -> &mut Self {
+        self.mode = value;
+        self
+    }
+    pub fn interpolation(&mut self, value: Interpolation) -> &mut Self {
+        self.interpolation = value;
+        self
+    }
+    pub fn gated(&mut self, value: bool) -> &mut Self {
+        self.gated = value;
+        self
+    }
+    build!(rate);
+    build!(amplitude);
+    build!(start);
+    build!(end);
+    build!(gate);
+    build!(trigger_in);
+    pub fn rack(
+        &self,
+        rack: &mut Rack,
+        controls: &mut Controls,
+        buffers: &mut Buffers,
+    ) -> Arc<Sampler> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.rate;
+        controls[(n, 1)] = self.amplitude;
+        controls[(n, 2)] = self.start;
+        controls[(n, 3)] = self.end;
+        controls[(n, 4)] = self.gate;
+        controls[(n, 5)] = self.trigger_in;
+        buffers.set_buffer(n.into(), RingBuffer::new(0, self.samples.clone()));
+        let sampler = Arc::new(Sampler::new(
+            n,
+            self.sample_rate,
+            self.mode,
+            self.interpolation,
+            self.gated,
+        ));
+        rack.push(sampler.clone());
+        sampler
+    }
+}
+
+/// Maximum number of grains a `Granular` module plays at once.
+pub const MAX_GRAINS: usize = 12;
+
+/// Tukey window, `shape` 0 is rectangular and 1 a Hann window.
+fn tukey(x: f32, shape: f32) -> f32 {
+    let edge = shape / 2.0;
+    if x < edge {
+        0.5 * (1.0 - (PI * x / edge).cos())
+    } else if x > 1.0 - edge {
+        0.5 * (1.0 - (PI * (1.0 - x) / edge).cos())
+    } else {
+        1.0
+    }
+}
+
+/// A granular synthesizer reading its source from the module's `RingBuffer`,
+/// either a fixed sample or, when built with `live`, the last few seconds of
+/// another module. Grains of `size` seconds are spawned `density` times per
+/// second at `position` (a fraction of the buffer, measured back from the
+/// newest sample for live input) plus a random offset of up to `jitter`.
+/// `pitch` is the playback rate, `shape` the Tukey window parameter and
+/// `spread` how far grains are randomly panned. Output 0 is the mono sum and
+/// outputs 1 and 2 are the left and right channels.
+#[derive(Clone)]
+pub struct Granular {
+    tag: Tag,
+    input: Option<Tag>,
+    sample_rate: f32,
+}
+
+impl Granular {
+    pub fn new<T: Into<Tag>>(tag: T, input: Option<Tag>, sample_rate: f32) -> Self {
+        Self {
+            tag: tag.into(),
+            input,
+            sample_rate,
+        }
+    }
+    props!(size, set_size, 0);
+    props!(density, set_density, 1);
+    props!(position, set_position, 2);
+    props!(jitter, set_jitter, 3);
+    props!(pitch, set_pitch, 4);
+    props!(shape, set_shape, 5);
+    props!(spread, set_spread, 6);
+    props!(amplitude, set_amplitude, 7);
+
+    /// The number of grains currently playing.
+    pub fn active(&self, state: &State) -> usize {
+        (0..MAX_GRAINS)
+            .filter(|g| state[(self.tag, 2 + 5 * g + 1)] < state[(self.tag, 2 + 5 * g + 2)])
+            .count()
+    }
+
+    fn spawn(&self, controls: &Controls, state: &mut State, outputs: &Outputs, len: f32, wp: f32) {
+        let tag = self.tag;
+        let grain = match (0..MAX_GRAINS)
+            .find(|g| state[(tag, 2 + 5 * g + 1)] >= state[(tag, 2 + 5 * g + 2)])
+        {
+            Some(g) => 2 + 5 * g,
+            None => return,
+        };
+        let offset = self.jitter(controls, outputs) * (2.0 * random(state, tag, 1) - 1.0);
+        let position = (self.position(controls, outputs) + offset).clamp(0.0, 1.0) * (len - 1.0);
+        state[(tag, grain)] = match self.input {
+            Some(_) => wp - position,
+            None => position,
+        };
+        state[(tag, grain + 1)] = 0.0;
+        state[(tag, grain + 2)] = self.size(controls, outputs) * self.sample_rate;
+        state[(tag, grain + 3)] = self.pitch(controls, outputs);
+        state[(tag, grain + 4)] =
+            self.spread(controls, outputs) * (2.0 * random(state, tag, 1) - 1.0);
+    }
+}
+
+impl Signal for Granular {
+    tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        self.input.into_iter().collect()
+    }
+    fn signal(
+        &self,
+        controls: &Controls,
+        state: &mut State,
+        outputs: &mut Outputs,
+        buffers: &mut Buffers,
+        sample_rate: f32,
+    ) {
+        let tag = self.tag;
+        if let Some(input) = self.input {
+            buffers.buffers_mut(tag).push(outputs[(input, 0)]);
+        }
+        let buffer = buffers.buffers(tag);
+        let len = buffer.len() as f32;
+        let wp = buffer.read_pos(0.0);
+        state[(tag, 0)] += self.density(controls, outputs) / sample_rate;
+        while state[(tag, 0)] >= 1.0 {
+            state[(tag, 0)] -= 1.0;
+            self.spawn(controls, state, outputs, len, wp);
+        }
+        let shape = self.shape(controls, outputs);
+        let (mut mono, mut left, mut right) = (0.0, 0.0, 0.0);
+        for g in 0..MAX_GRAINS {
+            let grain = 2 + 5 * g;
+            let (pos, age, length) = (
+                state[(tag, grain)],
+                state[(tag, grain + 1)],
+                state[(tag, grain + 2)],
+            );
+            if age >= length {
+                continue;
+            }
+            let v = tukey(age / length, shape) * buffer.get_linear(wp - pos);
+            let pan = (state[(tag, grain + 4)] + 1.0) * PI / 4.0;
+            mono += v;
+            left += v * pan.cos();
+            right += v * pan.sin();
+            state[(tag, grain)] = pos + state[(tag, grain + 3)] * self.sample_rate / sample_rate;
+            state[(tag, grain + 1)] = age + 1.0;
+        }
+        let amp = self.amplitude(controls, outputs);
+        outputs[(tag, 0)] = amp * mono;
+        outputs[(tag, 1)] = amp * left;
+        outputs[(tag, 2)] = amp * right;
+    }
+}
+
+#[derive(Clone)]
+pub struct GranularBuilder {
+    samples: Vec<f32>,
+    sample_rate: f32,
+    input: Option<Tag>,
+    size: Control,
+    density: Control,
+    position: Control,
+    jitter: Control,
+    pitch: Control,
+    shape: Control,
+    spread: Control,
+    amplitude: Control,
+}
+
+impl GranularBuilder {
+    /// Grains from `samples` recorded at `sample_rate`.
+    pub fn new(samples: Vec<f32>, sample_rate: f32) -> Self {
+        assert!(
+            !samples.is_empty(),
+            "A granular source needs at least one sample"
+        );
+        Self {
+            samples,
+            sample_rate,
+            input: None,
+            size: 0.1.into(),
+            density: 20.0.into(),
+            position: 0.0.into(),
+            jitter: 0.0.into(),
+            pitch: 1.0.into(),
+            shape: 1.0.into(),
+            spread: 0.0.into(),
+            amplitude: 1.0.into(),
+        }
+    }
+    /// Grains from the last `seconds` of the output of `wave`.
+    pub fn live(wave: Tag, seconds: f32, sample_rate: f32) -> Self {
+        let mut builder = Self::new(vec![0.0; (seconds * sample_rate) as usize + 1], sample_rate);
+        builder.input = Some(wave);
+        builder
+    }
+    /// Load the source from a wav file, mixed down to mono.
+    #[cfg(feature = "wav")]
+    pub fn from_wav<P: AsRef<Path>>(path: P) -> Result<Self, hound::Error> {
+        let (samples, sample_rate) = read_wav(path)?;
+        Ok(Self::new(samples, sample_rate))
+    }
+    build!(size);
+    build!(density);
+    build!(position);
+    build!(jitter);
+    build!(pitch);
+    build!(shape);
+    build!(spread);
+    build!(amplitude);
+    pub fn rack(
+        &self,
+        rack: &mut Rack,
+        controls: &mut Controls,
+        buffers: &mut Buffers,
+    ) -> Arc<Granular> {
+        let n = rack.num_modules();
+        controls[(n, 0)] = self.size;
+        controls[(n, 1)] = self.density;
+        controls[(n, 2)] = self.position;
+        controls[(n, 3)] = self.jitter;
+        controls[(n, 4)] = self.pitch;
+        controls[(n, 5)] = self.shape;
+        controls[(n, 6)] = self.spread;
+        controls[(n, 7)] = self.amplitude;
+        buffers.set_buffer(n.into(), RingBuffer::new(0, self.samples.clone()));
+        let granular = Arc::new(Granular::new(n, self.input, self.sample_rate));
+        rack.push(granular.clone());
+        granular
+    }
+}
@@ -1,7 +1,9 @@
 use crate::rack::*;
 use crate::{props, tag};
-use std::f32::consts::PI;
-use std::sync::Arc;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num::Float;
 
 #[derive(Debug, Copy, Clone)]
 pub struct SineFold {
@@ -20,6 +22,9 @@ impl SineFold {
 
 impl Signal for SineFold {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
 
     fn signal(
         &self,
@@ -68,6 +73,9 @@ impl Tanh {
 
 impl Signal for Tanh {
     tag!();
+    fn inputs(&self, _controls: &Controls) -> Vec<Tag> {
+        vec![self.wave]
+    }
 
     fn signal(
         &self,
@@ -1,5 +1,11 @@
 use crate::rack::*;
+use alloc::{vec, vec::Vec};
 use approx::relative_eq;
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num::Float;
+#[cfg(feature = "wav")]
+use std::path::Path;
 
 /// Given f(0) = low, f(1/2) = mid, and f(1) = high, let f(x) = a + b*exp(cs).
 /// Fit a, b, and c so to match the above. If mid < 1/2(high + low) then f is
@@ -46,6 +52,91 @@ pub fn signals(
     result
 }
 
+/// Bijective integer mixer from the `lowbias32` hash.
+fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+/// A pseudo random number in [0, 1) from a generator whose state is kept in
+/// `state[(tag, slot)]`. The state is a 24 bit linear congruential generator,
+/// so it is stored exactly as an `f32`, and the output is mixed with the tag
+/// so that each module gets its own sequence.
+pub fn random(state: &mut State, tag: Tag, slot: usize) -> f32 {
+    let x = (state[(tag, slot)] as u32)
+        .wrapping_mul(1_140_671_485)
+        .wrapping_add(12_820_163)
+        & 0x00ff_ffff;
+    state[(tag, slot)] = x as f32;
+    let key = mix(usize::from(tag) as u32 ^ 0x9e37_79b9);
+    (mix(x ^ key) >> 8) as f32 / 16_777_216.0
+}
+
+/// In place radix 2 fast Fourier transform of the complex signal `re + i im`,
+/// whose length must be a power of 2. The inverse is not scaled by `1 / n`.
+pub fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let n = re.len();
+    assert!(n.is_power_of_two(), "fft length must be a power of 2");
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let theta = sign * 2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (theta * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Read a wav file, mixing all of its channels down to mono. Returns the
+/// samples and the sample rate of the file.
+#[cfg(feature = "wav")]
+pub fn read_wav<P: AsRef<Path>>(path: P) -> Result<(Vec<f32>, f32), hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1u32 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels as usize;
+    let mono = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok((mono, spec.sample_rate as f32))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -55,6 +146,38 @@ mod tests {
         (10_000.0 * x + 0.5) as i32
     }
     #[test]
+    fn fft_round_trip() {
+        let signal = [1.0, 2.0, 0.0, -1.0, 0.5, 0.0, 0.0, 3.0];
+        let mut re = signal;
+        let mut im = [0.0; 8];
+        fft(&mut re, &mut im, false);
+        assert_eq!(trunc4(re[0]), trunc4(5.5), "dc is {}, expected 5.5", re[0]);
+        fft(&mut re, &mut im, true);
+        for (x, y) in re.iter().zip(signal.iter()) {
+            assert_eq!(
+                trunc4(x / 8.0),
+                trunc4(*y),
+                "got {}, expected {}",
+                x / 8.0,
+                y
+            );
+        }
+    }
+    #[test]
+    fn random_per_module() {
+        let mut state = State::new();
+        let draw = |state: &mut State, tag: usize| -> Vec<f32> {
+            (0..64).map(|_| random(state, Tag(tag), 0)).collect()
+        };
+        let a = draw(&mut state, 1);
+        let b = draw(&mut state, 2);
+        assert!(a.iter().all(|x| (0.0..1.0).contains(x)));
+        assert!(a.iter().zip(&b).all(|(x, y)| x != y));
+        assert!(a[1..].iter().zip(&b).all(|(x, y)| x != y));
+        let mean = a.iter().chain(&b).sum::<f32>() / 128.0;
+        assert!((mean - 0.5).abs() < 0.1, "mean is {}", mean);
+    }
+    #[test]
     fn linear_interp() {
         fn ie(x: f32) -> f32 {
             interp(0.0, 0.5, 1.0, x)
@@ -0,0 +1,123 @@
+use oscen::filters::*;
+use oscen::oscillators::*;
+use oscen::rack::*;
+
+#[test]
+fn ladder() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let input = ConstBuilder::new(0.01.into()).rack(&mut rack, &mut controls);
+    let ladder = LadderBuilder::new(input.tag())
+        .resonance(1.2)
+        .rack(&mut rack, &mut controls);
+    let (mut lo, mut hi) = (0.0f32, 0.0f32);
+    for _ in 0..44100 {
+        let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+        assert!(r.abs() <= 1.0, "ladder output {} is unbounded", r);
+        lo = lo.min(r);
+        hi = hi.max(r);
+    }
+    assert!(hi - lo > 0.2, "ladder did not self oscillate");
+    let mut r = 0.0;
+    ladder.set_resonance(&mut controls, 0.0.into());
+    for _ in 0..44100 {
+        r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    }
+    assert!((r - 0.01).abs() < 1e-4, "ladder dc gain is {}", r / 0.01);
+}
+
+#[test]
+fn svf() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let input = ConstBuilder::new(1.0.into()).rack(&mut rack, &mut controls);
+    let svf = SvfBuilder::new(input.tag())
+        .morph(0.75)
+        .rack(&mut rack, &mut controls);
+    let mut out = [0.0; MAX_OUTPUTS];
+    for _ in 0..4410 {
+        out = rack.play(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    }
+    let rs: Vec<i32> = out[..6]
+        .iter()
+        .map(|r| (r * 1000.0).round() as i32)
+        .collect();
+    assert_eq!(rs, vec![0, 1000, 0, 0, 1000, 1000]);
+    svf.set_morph(&mut controls, 0.25.into());
+    let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    assert!((r - 0.5).abs() < 1e-3);
+}
+
+#[test]
+fn eq() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let input = ConstBuilder::new(1.0.into()).rack(&mut rack, &mut controls);
+    let shelf = low_shelf(input.tag())
+        .gain(6.0)
+        .rack(&mut rack, &mut controls);
+    let tilted = tilt(input.tag()).gain(6.0).rack(&mut rack, &mut controls);
+    let peaked = peak(input.tag()).gain(6.0).rack(&mut rack, &mut controls);
+    let eq = ParametricEqBuilder::new(input.tag(), vec![EqShape::LowShelf, EqShape::HighShelf])
+        .gain(0, 6.0)
+        .gain(1, 6.0)
+        .bypass(1, 1.0)
+        .rack(&mut rack, &mut controls);
+    for _ in 0..4410 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    }
+    let rs: Vec<i32> = [shelf.tag(), tilted.tag(), peaked.tag(), eq.tag()]
+        .iter()
+        .map(|t| (outputs[(*t, 0)] * 1000.0).round() as i32)
+        .collect();
+    assert_eq!(rs, vec![1995, 708, 1000, 1995]);
+}
+
+#[test]
+fn eq_bypass() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let input = ConstBuilder::new(1.0.into()).rack(&mut rack, &mut controls);
+    let gate = ConstBuilder::new(0.0.into()).rack(&mut rack, &mut controls);
+    let eq = ParametricEqBuilder::new(input.tag(), vec![EqShape::LowShelf])
+        .gain(0, 6.0)
+        .bypass(0, gate.tag())
+        .rack(&mut rack, &mut controls);
+    let mut run = |controls: &Controls, n: usize| -> Vec<f32> {
+        (0..n)
+            .map(|_| rack.mono(controls, &mut state, &mut outputs, &mut buffers, 44100f32))
+            .collect()
+    };
+    let wet = *run(&controls, 4410).last().unwrap();
+    assert!((wet - 1.995).abs() < 0.001);
+    // Both ways the output glides between the filtered and dry signal.
+    let mut last = wet;
+    for (value, settled) in [(1.0, 1.0), (0.0, wet)].iter() {
+        gate.set_value(&mut controls, (*value).into());
+        let rs = run(&controls, 441);
+        assert!((rs[0] - last).abs() < 0.01);
+        assert!(rs.windows(2).all(|w| (w[1] - w[0]).abs() < 0.01));
+        last = rs[rs.len() - 1];
+        assert!((last - settled).abs() < 0.001);
+    }
+    assert_eq!(eq.bypass(&controls, &outputs, 0), 0.0);
+}
+
+#[test]
+fn lpf_cutoff_change() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let sine = OscBuilder::new(sine_osc)
+        .hz(1000.0)
+        .rack(&mut rack, &mut controls, &mut state);
+    let changed = LpfBuilder::new(sine.tag())
+        .cut_off(100.0)
+        .rack(&mut rack, &mut controls);
+    let fixed = LpfBuilder::new(sine.tag())
+        .cut_off(5000.0)
+        .rack(&mut rack, &mut controls);
+    for _ in 0..1000 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    }
+    changed.set_cutoff(&mut controls, 5000.0.into());
+    for _ in 0..1000 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    }
+    let diff = outputs[(changed.tag(), 0)] - outputs[(fixed.tag(), 0)];
+    assert!(diff.abs() < 1e-3, "cached coefficients were not updated");
+}
@@ -0,0 +1,35 @@
+use oscen::instruments::*;
+use oscen::rack::*;
+
+#[test]
+fn fm_voice() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let voice = FmVoiceBuilder::new(4)
+        .hz(0.25)
+        .level(1, 0.125)
+        .level(2, 0.0)
+        .level(3, 0.0)
+        .rack(&mut rack, &mut controls);
+    voice.on(&mut controls, &mut state);
+    let r1 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r2 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let rs: Vec<i32> = [r1, r2]
+        .iter()
+        .map(|r| (r * 1000.0).round() as i32)
+        .collect();
+    assert_eq!(rs, vec![0, 707]);
+    voice.set_algorithm(&mut controls, 32);
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert!(Algorithm::dx7(5).is_carrier(4));
+    assert!(Algorithm::dx7(5).modulates(5, 4));
+    assert!(Algorithm::dx7(5).feeds_back(5, 5));
+    assert!(!Algorithm::dx7(4).modulates(3, 5));
+    assert!(Algorithm::dx7(4).feeds_back(3, 5));
+    assert!(!Algorithm::dx7(4).feeds_back(5, 5));
+}
+
+#[test]
+#[should_panic(expected = "Operators are numbered from 0 to 5, not 6")]
+fn algorithm_operator_out_of_range() {
+    Algorithm::new(&[0], &[(6, 0)]);
+}
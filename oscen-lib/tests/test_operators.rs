@@ -1,6 +1,8 @@
 use oscen::operators::*;
 use oscen::oscillators::*;
 use oscen::rack::*;
+use oscen::shaping::*;
+use std::f32::consts::PI;
 
 #[test]
 fn mixer() {
@@ -60,6 +62,65 @@ fn cross() {
     assert_eq!(r, 2.25);
 }
 
+#[test]
+fn mod_matrix() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let c2 = ConstBuilder::new(2.0.into()).rack(&mut rack, &mut controls);
+    let c3 = ConstBuilder::new(3.0.into()).rack(&mut rack, &mut controls);
+    let m = ModMatrixBuilder::new(vec![c2.tag(), c3.tag()], 2)
+        .base(0, 1.0)
+        .depth(0, 0, 0.5)
+        .depth(1, 0, 1.0)
+        .depth(1, 1, 2.0)
+        .rack(&mut rack, &mut controls, &mut buffers);
+    let r = rack.play(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_eq!((r[0], r[1]), (5.0, 6.0));
+    m.set_depth(&mut buffers, 0, 1, -1.0);
+    let r = rack.play(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_eq!((r[0], r[1]), (5.0, 4.0));
+}
+
+#[test]
+fn large_mod_matrix() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let sources: Vec<Tag> = (0..16)
+        .map(|i| {
+            ConstBuilder::new((i as f32).into())
+                .rack(&mut rack, &mut controls)
+                .tag()
+        })
+        .collect();
+    let mut builder = ModMatrixBuilder::new(sources, 16);
+    for j in 0..16 {
+        builder.depth(15 - j, j, 1.0);
+    }
+    let m = builder.rack(&mut rack, &mut controls, &mut buffers);
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let rs: Vec<f32> = (0..16).map(|j| outputs[(m.tag(), j)]).collect();
+    assert_eq!(rs, (0..16).rev().map(|i| i as f32).collect::<Vec<_>>());
+}
+
+#[test]
+fn latency_compensation() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let clock = ClockBuilder::new(8.0).rack(&mut rack, &mut controls);
+    let delay =
+        DelayBuilder::new(clock.tag(), 3.0.into()).rack(&mut rack, &mut controls, &mut buffers);
+    let mix = MixerBuilder::new(vec![clock.tag(), delay.tag()]).rack_compensated(
+        &mut rack,
+        &mut controls,
+        &outputs,
+        &mut buffers,
+        1f32,
+    );
+    let latencies = rack.latencies(&controls, &outputs, &buffers, 1f32);
+    assert_eq!(latencies[usize::from(mix.tag())], 3.0);
+    let rs: Vec<f32> = (0..5)
+        .map(|_| rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32))
+        .collect();
+    assert_eq!(rs, vec![0.0, 0.0, 0.0, 2.0, 0.0]);
+}
+
 #[test]
 fn modulator() {
     let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
@@ -71,3 +132,126 @@ fn modulator() {
     let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
     assert_eq!(r, 3740.0);
 }
+
+#[test]
+fn oversampler() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let c = ConstBuilder::new(1.0.into()).rack(&mut rack, &mut controls);
+    let mut builder = OversamplerBuilder::new(c.tag(), 4);
+    let input = builder.input(&mut rack, &mut buffers);
+    let vca = VcaBuilder::new(input.tag())
+        .level(0.5)
+        .rack(&mut rack, &mut controls);
+    builder.rack(&mut rack, &mut buffers, vca.tag());
+    let mut r = 0.0;
+    for _ in 0..32 {
+        r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    }
+    assert!((r - 0.5).abs() < 1e-4, "oversampler returned {}", r);
+}
+
+/// Power of `xs` in the DFT bin at `hz`.
+fn power(xs: &[f32], hz: f32, sample_rate: f32) -> f32 {
+    let (re, im) = xs.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, x)| {
+        let (s, c) = (2.0 * PI * hz * i as f32 / sample_rate).sin_cos();
+        (re + x * c, im - x * s)
+    });
+    (re * re + im * im) / (xs.len() * xs.len()) as f32
+}
+
+#[test]
+fn oversampler_aliasing() {
+    // The third harmonic of a 10 kHz sine aliases to 18 kHz at a 48 kHz
+    // sample rate.
+    let sample_rate = 48_000.0;
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let sine = OscBuilder::new(sine_osc).hz(10_000.0).amplitude(0.2).rack(
+        &mut rack,
+        &mut controls,
+        &mut state,
+    );
+    let direct = TanhBuilder::new(sine.tag()).rack(&mut rack);
+    let mut builder = OversamplerBuilder::new(sine.tag(), 4);
+    let input = builder.input(&mut rack, &mut buffers);
+    let tanh = TanhBuilder::new(input.tag()).rack(&mut rack);
+    let oversampled = builder.rack(&mut rack, &mut buffers, tanh.tag());
+    let mut xs = vec![];
+    let mut ys = vec![];
+    for i in 0..5_000 {
+        rack.mono(
+            &controls,
+            &mut state,
+            &mut outputs,
+            &mut buffers,
+            sample_rate,
+        );
+        if i >= 200 {
+            xs.push(outputs[(direct.tag(), 0)]);
+            ys.push(outputs[(oversampled.tag(), 0)]);
+        }
+    }
+    let aliased = power(&xs, 18_000.0, sample_rate);
+    let filtered = power(&ys, 18_000.0, sample_rate);
+    assert!(
+        filtered < 0.01 * aliased,
+        "aliased power {} oversampled, {} direct",
+        filtered,
+        aliased
+    );
+    assert!(power(&ys, 10_000.0, sample_rate) > 0.01);
+}
+
+#[test]
+fn clock_divider_multiplier() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let clock = ClockBuilder::new(4.0).rack(&mut rack, &mut controls);
+    let divider = ClockDividerBuilder::new(clock.tag()).rack(&mut rack, &mut controls);
+    let multiplier = ClockMultiplierBuilder::new(clock.tag()).rack(&mut rack, &mut controls);
+    let never = ClockSkipBuilder::new(clock.tag())
+        .probability(1.0)
+        .rack(&mut rack, &mut controls);
+    let always = ClockSkipBuilder::new(clock.tag())
+        .probability(0.0)
+        .rack(&mut rack, &mut controls);
+    let mut rs = vec![];
+    for _ in 0..12 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+        let out = |t: Tag| outputs[(t, 0)] as i32;
+        rs.push([
+            out(clock.tag()),
+            out(divider.tag()),
+            out(multiplier.tag()),
+            out(never.tag()),
+            out(always.tag()),
+        ]);
+    }
+    let column = |i: usize| rs.iter().map(|r| r[i]).collect::<Vec<_>>();
+    assert_eq!(column(0), vec![1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(column(1), vec![1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(column(2), vec![1, 0, 0, 0, 1, 0, 1, 0, 1, 0, 1, 0]);
+    assert_eq!(column(3), vec![0; 12]);
+    assert_eq!(column(4), column(0));
+    assert_eq!(multiplier.period(&state), 4.0);
+}
+
+#[test]
+fn clock_skip_high_tag() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    for _ in 0..300 {
+        ConstBuilder::new(0.0.into()).rack(&mut rack, &mut controls);
+    }
+    let clock = ClockBuilder::new(2.0).rack(&mut rack, &mut controls);
+    let skip = ClockSkipBuilder::new(clock.tag()).rack(&mut rack, &mut controls);
+    let mut passed = vec![];
+    for i in 0..800 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+        if i % 2 == 0 {
+            passed.push(outputs[(skip.tag(), 0)]);
+        }
+    }
+    let total: f32 = passed.iter().sum();
+    assert!((150.0..250.0).contains(&total), "{} of 400 passed", total);
+    // Consecutive pulses are decided independently, not in pairs.
+    let pairs = passed.chunks(2).filter(|p| p[0] == p[1]).count();
+    assert!((60..140).contains(&pairs), "{} of 200 pairs matched", pairs);
+}
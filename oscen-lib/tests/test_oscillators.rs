@@ -13,6 +13,482 @@ fn osc() {
     assert_eq!((r1, r2, r3), (7.0, 7.5, 7.0));
 }
 
+#[test]
+fn band_limited() {
+    assert_eq!(saw_blep(0.5, 0.0, 0.01), saw_osc(0.5, 0.0));
+    assert_eq!(square_blep(0.25, 0.5, 0.01), square_osc(0.25, 0.5));
+    assert_eq!(triangle_blep(0.5, 0.0, 0.01), triangle_osc(0.5, 0.0));
+    assert_eq!(square_blep(0.0, 0.5, 0.01), 0.0);
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    OscBuilder::band_limited(saw_blep)
+        .hz(0.25)
+        .rack(&mut rack, &mut controls, &mut state);
+    let r1 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r2 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r3 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_eq!((r1, r2, r3), (0.0, 0.5, 0.0));
+}
+
+#[test]
+fn sync_reset() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let osc = OscBuilder::new(saw_osc)
+        .hz(0.125)
+        .rack(&mut rack, &mut controls, &mut state);
+    let mut next = |controls: &Controls| {
+        let r = rack.mono(controls, &mut state, &mut outputs, &mut buffers, 1f32);
+        (r * 1000.0).round() as i32
+    };
+    let r1 = next(&controls);
+    let r2 = next(&controls);
+    osc.set_reset(&mut controls, 1.0.into());
+    let r3 = next(&controls);
+    let r4 = next(&controls);
+    osc.set_sync(&mut controls, (-1.0).into());
+    next(&controls);
+    osc.set_sync(&mut controls, 3.0.into());
+    let r5 = next(&controls);
+    assert_eq!(vec![r1, r2, r3, r4, r5], vec![-1000, 750, -1000, 750, 813]);
+}
+
+#[test]
+fn phase_and_frequency_modulation() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let osc =
+        OscBuilder::new(sine_osc)
+            .pm(0.25)
+            .fm(-0.25)
+            .rack(&mut rack, &mut controls, &mut state);
+    let r1 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r2 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    osc.set_pm(&mut controls, 0.0.into());
+    let r3 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let rs: Vec<i32> = [r1, r2, r3]
+        .iter()
+        .map(|r| (r * 1000.0).round() as i32)
+        .collect();
+    assert_eq!(rs, vec![1000, 0, 0]);
+    assert_eq!(osc.phase(&state), 0.25);
+}
+
+#[test]
+fn additive() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let osc = AdditiveBuilder::new(vec![1.0.into(), 0.5.into()])
+        .hz(0.125)
+        .rack(&mut rack, &mut controls);
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r1 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    // The second harmonic is above the Nyquist frequency.
+    osc.set_hz(&mut controls, 0.375.into());
+    let r2 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let rs: Vec<i32> = [r1, r2]
+        .iter()
+        .map(|r| (r * 1000.0).round() as i32)
+        .collect();
+    assert_eq!(rs, vec![1207, 1000]);
+    // Every partial is above the Nyquist frequency, however high hz gets.
+    osc.set_hz(&mut controls, f32::INFINITY.into());
+    let r3 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_eq!(r3, 0.0);
+}
+
+#[test]
+fn random_sample_hold() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    RandomBuilder::new(RandomMode::SampleHold)
+        .rate(0.25)
+        .rack(&mut rack, &mut controls);
+    let rs: Vec<f32> = (0..8)
+        .map(|_| rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32))
+        .collect();
+    assert!(rs.iter().all(|r| r.abs() <= 1.0));
+    assert!(rs[..4].iter().all(|r| *r == rs[0]));
+    assert!(rs[4..].iter().all(|r| *r == rs[4]));
+
+    // A new value is drawn on each wrap, keeping the overshoot.
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let random = RandomBuilder::new(RandomMode::SampleHold)
+        .rate(0.375)
+        .rack(&mut rack, &mut controls);
+    let rs: Vec<f32> = (0..16)
+        .map(|_| rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32))
+        .collect();
+    let changes: Vec<usize> = (1..16).filter(|&i| rs[i] != rs[i - 1]).collect();
+    assert_eq!(changes, vec![3, 6, 8, 11, 14]);
+
+    // At rate 0 the value is held.
+    random.set_rate(&mut controls, 0.0.into());
+    let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    for _ in 0..8 {
+        assert_eq!(
+            rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32),
+            r
+        );
+    }
+}
+
+/// Lag one autocorrelation, positive for noise with more energy in the low
+/// frequencies and negative for noise with more in the high frequencies.
+fn autocorrelation(xs: &[f32]) -> f32 {
+    let mean = xs.iter().sum::<f32>() / xs.len() as f32;
+    let var: f32 = xs.iter().map(|x| (x - mean) * (x - mean)).sum();
+    let cov: f32 = xs.windows(2).map(|w| (w[0] - mean) * (w[1] - mean)).sum();
+    cov / var
+}
+
+#[test]
+fn colored_noise() {
+    let rho = |color| {
+        let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+        ColoredNoiseBuilder::new(color).rack(&mut rack, &mut controls);
+        let xs: Vec<f32> = (0..20000)
+            .map(|_| rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100.0))
+            .collect();
+        assert!(xs.iter().all(|x| x.abs() <= 1.0));
+        autocorrelation(&xs)
+    };
+    let (brown, blue, violet) = (
+        rho(NoiseColor::Brown),
+        rho(NoiseColor::Blue),
+        rho(NoiseColor::Violet),
+    );
+    assert!(brown > 0.9, "brown {}", brown);
+    assert!(blue < -0.1, "blue {}", blue);
+    // Differentiated white noise has an autocorrelation of exactly -0.5.
+    assert!((violet + 0.5).abs() < 0.05, "violet {}", violet);
+    assert!(violet < blue);
+}
+
+#[test]
+fn velvet_noise() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    VelvetNoiseBuilder::new()
+        .density(1000.0)
+        .amplitude(0.5)
+        .rack(&mut rack, &mut controls);
+    let xs: Vec<f32> = (0..44100)
+        .map(|_| rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100.0))
+        .collect();
+    let impulses: Vec<f32> = xs.iter().copied().filter(|x| *x != 0.0).collect();
+    assert!(impulses.iter().all(|x| x.abs() == 0.5));
+    // One impulse in each period of 45 samples, with both signs.
+    assert_eq!(impulses.len(), 980);
+    let positive = impulses.iter().filter(|x| **x > 0.0).count();
+    assert!(positive > 400 && positive < 580, "{} positive", positive);
+    for period in xs.chunks(45) {
+        assert_eq!(period.iter().filter(|x| **x != 0.0).count(), 1);
+    }
+}
+
+#[test]
+fn chaotic() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let lorenz = ChaoticBuilder::new(Attractor::Lorenz).rate(0.01).rack(
+        &mut rack,
+        &mut controls,
+        &mut state,
+    );
+    let logistic =
+        LogisticBuilder::new()
+            .r(2.0)
+            .x0(0.25)
+            .rack(&mut rack, &mut controls, &mut state);
+    let mut rs = vec![];
+    for _ in 0..3 {
+        rs.push(rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32));
+    }
+    assert_eq!(rs, vec![-0.5, -0.25, -0.0625]);
+    lorenz.set_rate(&mut controls, 100.0.into());
+    for _ in 0..44100 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    }
+    for i in 0..3 {
+        assert!(outputs[(lorenz.tag(), i)].abs() < 2.0);
+    }
+    for r in [3.5, 3.9, 4.0, 5.0].iter() {
+        logistic.set_r(&mut controls, (*r).into());
+        for _ in 0..1000 {
+            rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+            let x = outputs[(logistic.tag(), 0)];
+            assert!((-1.0..=1.0).contains(&x), "logistic left [-1, 1]: {}", x);
+        }
+    }
+    // Above 4 it keeps behaving like r = 4 rather than falling silent.
+    let x = outputs[(logistic.tag(), 0)];
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_ne!(outputs[(logistic.tag(), 0)], x);
+
+    // Both start over after blowing up.
+    lorenz.set_rate(&mut controls, 1e30.into());
+    logistic.set_r(&mut controls, f32::NAN.into());
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    lorenz.set_rate(&mut controls, 0.01.into());
+    logistic.set_r(&mut controls, 2.0.into());
+    for _ in 0..3 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    }
+    assert!((0..3).all(|i| outputs[(lorenz.tag(), i)].is_finite()));
+    assert!(outputs[(logistic.tag(), 0)].is_finite());
+}
+
+#[test]
+fn phase_distortion() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let pd = PhaseDistortionBuilder::new(pd_saw)
+        .hz(0.25)
+        .rack(&mut rack, &mut controls);
+    let mut next = |controls: &Controls| {
+        let r = rack.mono(controls, &mut state, &mut outputs, &mut buffers, 1f32);
+        (r * 1000.0).round() as i32
+    };
+    let r1 = next(&controls);
+    let r2 = next(&controls);
+    pd.set_amount(&mut controls, 0.5.into());
+    let r3 = next(&controls);
+    assert_eq!(vec![r1, r2, r3], vec![1000, 0, -500]);
+}
+
+#[test]
+fn formant() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    FormantBuilder::new()
+        .hz(110.0)
+        .rack(&mut rack, &mut controls);
+    let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    assert_eq!(r, 0.0);
+    let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 44100f32);
+    assert!(r != 0.0);
+    let formants = vowel_formants(0.5);
+    assert_eq!(formants[0].0, 525.0);
+    assert_eq!(formants[0].1, 1.0);
+}
+
+#[test]
+fn lfo() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let lfo = LfoBuilder::new()
+        .shape(LfoShape::SawUp)
+        .hz(0.25)
+        .rack(&mut rack, &mut controls);
+    let mut rs = vec![];
+    for _ in 0..3 {
+        rs.push(rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32));
+    }
+    lfo.set_unipolar(&mut controls, true);
+    lfo.set_gate(&mut controls, 1.0.into());
+    rs.push(rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32));
+    rs.push(rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32));
+    assert_eq!(rs, vec![-1.0, -0.5, 0.0, 0.0, 0.25]);
+
+    // The overshoot is kept when the phase wraps.
+    lfo.set_unipolar(&mut controls, false);
+    lfo.set_hz(&mut controls, 0.375.into());
+    lfo.set_gate(&mut controls, 0.0.into());
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    lfo.set_gate(&mut controls, 1.0.into());
+    let rs: Vec<f32> = (0..4)
+        .map(|_| rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32))
+        .collect();
+    assert_eq!(rs, vec![-1.0, -0.25, 0.5, -0.75]);
+}
+
+#[test]
+fn lfo_sample_hold() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let lfos: Vec<_> = (0..2)
+        .map(|_| {
+            LfoBuilder::new()
+                .shape(LfoShape::SampleHold)
+                .rack(&mut rack, &mut controls)
+        })
+        .collect();
+    let mut rs = vec![];
+    for _ in 0..32 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+        rs.push((outputs[(lfos[0].tag(), 0)], outputs[(lfos[1].tag(), 0)]));
+    }
+    // Neighbouring lfos do not share a sequence, not even shifted by a cycle.
+    assert!(rs.iter().all(|(a, b)| a != b));
+    assert!(rs[1..].iter().zip(&rs).all(|(a, b)| a.0 != b.1));
+    assert!(rs.iter().all(|(a, b)| a.abs() <= 1.0 && b.abs() <= 1.0));
+}
+
+#[test]
+fn lfo_sync() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let clock = ClockBuilder::new(2.0).rack(&mut rack, &mut controls);
+    let bpm = LfoBuilder::new()
+        .shape(LfoShape::SawUp)
+        .sync(LfoSync::Bpm)
+        .bpm(60.0)
+        .division(2.0)
+        .rack(&mut rack, &mut controls);
+    let lfo = LfoBuilder::new()
+        .shape(LfoShape::SawUp)
+        .sync(LfoSync::Clock)
+        .clock(clock.tag())
+        .division(2.0)
+        .rack(&mut rack, &mut controls);
+    let mut rs = vec![];
+    for _ in 0..6 {
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+        rs.push((outputs[(bpm.tag(), 0)], outputs[(lfo.tag(), 0)]));
+    }
+    // The clock lfo is stopped until it has seen two clock pulses.
+    assert_eq!(
+        rs,
+        vec![
+            (-1.0, -1.0),
+            (0.0, -1.0),
+            (-1.0, -1.0),
+            (0.0, -0.5),
+            (-1.0, 0.0),
+            (0.0, 0.5)
+        ]
+    );
+}
+
+#[test]
+fn tempo_clock() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let clock = TempoClockBuilder::new(60.0)
+        .beats_per_bar(2.0)
+        .division(2.0)
+        .ppqn(1.0)
+        .rack(&mut rack, &mut controls);
+    let mut rs = vec![];
+    for _ in 0..5 {
+        let out = rack.play(&controls, &mut state, &mut outputs, &mut buffers, 2f32);
+        rs.push(out[..5].to_vec());
+    }
+    assert_eq!(
+        rs,
+        vec![
+            vec![1.0, 1.0, 1.0, 1.0, 0.0],
+            vec![0.0, 0.0, 1.0, 0.0, 0.5],
+            vec![1.0, 0.0, 1.0, 1.0, 1.0],
+            vec![0.0, 0.0, 1.0, 0.0, 1.5],
+            vec![1.0, 1.0, 1.0, 1.0, 2.0],
+        ]
+    );
+    clock.set_swing(&mut controls, 1.0.into());
+    clock.set_reset(&mut controls, 1.0.into());
+    let swung: Vec<f32> = (0..5)
+        .map(|_| rack.play(&controls, &mut state, &mut outputs, &mut buffers, 4f32)[2])
+        .collect();
+    assert_eq!(swung, vec![1.0, 0.0, 0.0, 1.0, 1.0]);
+
+    // Late in a song the clock still keeps time.
+    clock.set_bpm(&mut controls, 120.0.into());
+    clock.set_position(&mut state, 4096.0);
+    let mut beats = 0.0;
+    for _ in 0..48_000 {
+        beats += rack.play(&controls, &mut state, &mut outputs, &mut buffers, 48_000f32)[0];
+    }
+    assert_eq!(beats, 2.0);
+    assert!((clock.position(&state) - 4098.0).abs() < 1e-3);
+
+    // The transport can be driven by a gate.
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let gate = ConstBuilder::new(0.0.into()).rack(&mut rack, &mut controls);
+    let clock = TempoClockBuilder::new(60.0)
+        .run(gate.tag())
+        .rack(&mut rack, &mut controls);
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 2f32);
+    assert_eq!(clock.position(&state), 0.0);
+    gate.set_value(&mut controls, 1.0.into());
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 2f32);
+    assert_eq!(clock.position(&state), 0.5);
+}
+
+#[test]
+#[cfg(feature = "wav")]
+fn wavetable_from_short_wav() {
+    let path = std::env::temp_dir().join("oscen_wavetable_from_short_wav.wav");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..4 {
+        writer.write_sample(i as f32 / 4.0).unwrap();
+    }
+    writer.finalize().unwrap();
+    assert!(WavetableBuilder::from_wav(&path, 0).is_err());
+    assert!(WavetableBuilder::from_wav(&path, 8).is_err());
+    assert!(WavetableBuilder::from_wav(&path, 4).is_ok());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn wavetable() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let sine: Vec<f32> = (0..64).map(|i| sine_osc(i as f32 / 64.0, 0.0)).collect();
+    let inverse = sine.iter().map(|x| -x).collect();
+    let wt = WavetableBuilder::new(vec![sine, inverse])
+        .hz(0.25)
+        .rack(&mut rack, &mut controls);
+    let r1 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r2 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    wt.set_position(&mut controls, 0.25.into());
+    let r3 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r4 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let rs: Vec<i32> = [r1, r2, r3, r4]
+        .iter()
+        .map(|r| (r * 100.0).round() as i32)
+        .collect();
+    assert_eq!(rs, vec![0, 100, 0, -50]);
+}
+
+#[test]
+fn unison() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let unison = UnisonBuilder::new(Waveform::Naive(sine_osc), 2)
+        .hz(0.25)
+        .detune(0.0)
+        .random_phase(0.0)
+        .rack(&mut rack, &mut controls, &mut state);
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let out = rack.play(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let rs: Vec<i32> = out[..3]
+        .iter()
+        .map(|r| (r * 1000.0).round() as i32)
+        .collect();
+    assert_eq!(rs, vec![1414, 707, 707]);
+    assert_eq!(unison.voices(), 2);
+
+    // The centre voice is never detuned, even with a flat curve.
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    UnisonBuilder::new(Waveform::Naive(sine_osc), 1)
+        .hz(0.25)
+        .detune(12.0)
+        .curve(0.0)
+        .random_phase(0.0)
+        .rack(&mut rack, &mut controls, &mut state);
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_eq!((r * 1000.0).round(), 1000.0);
+}
+
+#[test]
+fn fourier_above_nyquist() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let osc = FourierOscBuilder::new(vec![0.0, 0.0, 1.0])
+        .hz(0.3)
+        .lanczos(false)
+        .rack(&mut rack, &mut controls);
+    let r1 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    osc.set_hz(&mut controls, 0.125.into());
+    let r2 = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    // The second harmonic was muted, but its phase kept up with the
+    // fundamental.
+    assert_eq!(r1, 0.0);
+    assert_eq!((r2 * 1000.0).round(), -588.0);
+}
+
 #[test]
 fn cnst() {
     let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
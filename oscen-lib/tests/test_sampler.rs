@@ -0,0 +1,84 @@
+use oscen::rack::*;
+use oscen::sampler::*;
+
+#[test]
+fn sampler() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let sampler = SamplerBuilder::new(vec![0.0, 1.0, 2.0, 3.0], 1.0)
+        .mode(PlayMode::PingPong)
+        .gate(1.0)
+        .rack(&mut rack, &mut controls, &mut buffers);
+    let mut rs = vec![];
+    for _ in 0..8 {
+        rs.push(rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32));
+    }
+    assert_eq!(rs, vec![0.0, 1.0, 2.0, 3.0, 2.0, 1.0, 0.0, 1.0]);
+    sampler.set_rate(&mut controls, 1.5.into());
+    sampler.set_gate(&mut controls, 0.0.into());
+    rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    sampler.set_gate(&mut controls, 1.0.into());
+    let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_eq!(r, 0.0);
+    let r = rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+    assert_eq!(r, 1.5);
+}
+
+#[test]
+fn one_shot() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let sampler = SamplerBuilder::new(vec![1.0, 2.0, 3.0, 4.0], 1.0)
+        .interpolation(Interpolation::None)
+        .start(0.5)
+        .rack(&mut rack, &mut controls, &mut buffers);
+    sampler.trigger(&mut state);
+    let mut rs = vec![];
+    for _ in 0..3 {
+        rs.push(rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32));
+    }
+    assert_eq!(rs, vec![3.0, 4.0, 0.0]);
+    assert!(!sampler.playing(&state));
+}
+
+#[test]
+fn interpolation_in_region() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let looped = SamplerBuilder::new(vec![0.0, 1.0, 2.0, 3.0], 1.0)
+        .mode(PlayMode::Forward)
+        .rate(0.5)
+        .start(0.5)
+        .rack(&mut rack, &mut controls, &mut buffers);
+    let clamped = SamplerBuilder::new(vec![0.0, 1.0, 2.0, 3.0], 1.0)
+        .rate(0.5)
+        .end(0.5)
+        .rack(&mut rack, &mut controls, &mut buffers);
+    let mut rs = vec![];
+    for i in 0..5 {
+        let trigger = if i == 0 { 1.0 } else { 0.0 };
+        looped.set_trigger_in(&mut controls, trigger.into());
+        clamped.set_trigger_in(&mut controls, trigger.into());
+        rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32);
+        rs.push((outputs[(looped.tag(), 0)], outputs[(clamped.tag(), 0)]));
+    }
+    assert_eq!(
+        rs,
+        vec![(2.0, 0.0), (2.5, 0.5), (3.0, 1.0), (2.5, 1.0), (2.0, 0.0)]
+    );
+}
+
+#[test]
+fn granular() {
+    let (mut rack, mut controls, mut state, mut outputs, mut buffers) = tables();
+    let granular = GranularBuilder::new(vec![1.0; 100], 1.0)
+        .size(4.0)
+        .density(1.0)
+        .shape(0.0)
+        .rack(&mut rack, &mut controls, &mut buffers);
+    let mut rs = vec![];
+    for _ in 0..6 {
+        rs.push(rack.mono(&controls, &mut state, &mut outputs, &mut buffers, 1f32));
+    }
+    assert_eq!(rs, vec![1.0, 2.0, 3.0, 4.0, 4.0, 4.0]);
+    assert_eq!(granular.active(&state), 3);
+    let left = outputs[(granular.tag(), 1)];
+    assert!((left - 4.0 * 0.5f32.sqrt()).abs() < 1e-6);
+}